@@ -0,0 +1,71 @@
+use log::warn;
+use ocrs::OcrEngine;
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{
+    apply_mode_requests, frame_delay, read_regions, reading_from_lines, Cycle, DetectionConfig,
+    EncounterError, EncounterRecord, EncounterState, FrameReading, Mode, MonFilter, ScreenSource,
+    StateStore,
+};
+
+/// `encounter_process` for a tokio runtime: waits between frames on a tokio timer and runs OCR
+/// on the blocking pool, so other tasks of the runtime keep running. A frame that takes longer
+/// than `frame_timeout_ms` skips the cycle like with `TimedOcr`. Capture stays on the calling
+/// task since sources can't move between threads, give it a `TimedSource` to bound it too.
+/// Static frames are always read.
+pub async fn encounter_process_async(
+    engine: &Arc<OcrEngine>,
+    state: &mut EncounterState,
+    source: &mut dyn ScreenSource,
+    config: &DetectionConfig,
+    filter: &dyn MonFilter,
+    store: &dyn StateStore,
+    control: &Receiver<Mode>,
+) -> Result<Option<EncounterRecord>, EncounterError> {
+    apply_mode_requests(state, control);
+    state.track_active_time();
+    if matches!(state.mode, Mode::Init | Mode::Pause) {
+        return Ok(None);
+    }
+
+    let mut cycle = Cycle::default();
+    for _ in 1..=config.detect_frames.max(1) {
+        let started = Instant::now();
+        let regions = match cycle.capture(state, source, config) {
+            Err(err @ EncounterError::CaptureTimedOut(_)) => {
+                warn!("cycle skipped: {}", err);
+                return Ok(None);
+            }
+            captured => captured?,
+        };
+        let engine = Arc::clone(engine);
+        let line_padding = config.line_padding;
+        let ocr =
+            tokio::task::spawn_blocking(move || read_regions(&engine, &regions, line_padding));
+        let joined = match config.frame_timeout_ms.map(Duration::from_millis) {
+            Some(timeout) => match tokio::time::timeout(timeout, ocr).await {
+                Ok(joined) => joined,
+                Err(_) => {
+                    warn!("cycle skipped: {}", EncounterError::OcrTimedOut(timeout));
+                    return Ok(None);
+                }
+            },
+            None => ocr.await,
+        };
+        let (lines, timings) = joined.map_err(EncounterError::ocr)??;
+        cycle.push(FrameReading {
+            timings,
+            ..reading_from_lines(lines, filter)
+        });
+        tokio::time::sleep(frame_delay(state, config, started)).await;
+
+        apply_mode_requests(state, control);
+        if matches!(state.mode, Mode::Init | Mode::Pause) {
+            return Ok(None);
+        }
+    }
+
+    cycle.finish(state, config, filter, store)
+}
@@ -0,0 +1,185 @@
+use image::DynamicImage;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use xcap::Window;
+
+use super::{game_exist, game_windows, screen_source, CaptureConfig, EncounterError, ScreenSource};
+
+struct Latest {
+    /// Bumped for every captured frame.
+    seq: u64,
+    frame: Result<DynamicImage, String>,
+    /// Set once the capture thread stopped, `frame` won't change anymore.
+    finished: bool,
+}
+
+struct Shared {
+    latest: Mutex<Latest>,
+    ready: Condvar,
+    stop: AtomicBool,
+}
+
+impl Shared {
+    fn publish(&self, frame: Result<DynamicImage, String>, finished: bool) {
+        if let Ok(mut latest) = self.latest.lock() {
+            latest.seq += 1;
+            latest.frame = frame;
+            latest.finished = finished;
+        }
+        self.ready.notify_all();
+    }
+}
+
+/// Keeps capturing on its own thread, so OCR never waits on a capture. `grab` returns the most
+/// recent frame, waiting only when it was already handed out. The thread stops on drop.
+pub struct BackgroundSource {
+    shared: Arc<Shared>,
+    last_seq: u64,
+}
+
+impl BackgroundSource {
+    /// Captures from the source `capture` selects every `interval`. Sources can't move between
+    /// threads, so the capture thread looks up the game window and creates its own.
+    pub fn start(capture: &CaptureConfig, interval: Duration) -> Self {
+        let shared = Arc::new(Shared {
+            latest: Mutex::new(Latest {
+                seq: 0,
+                frame: Err("no frame captured yet".to_string()),
+                finished: false,
+            }),
+            ready: Condvar::new(),
+            stop: AtomicBool::new(false),
+        });
+
+        let capture = CaptureConfig {
+            background_interval_ms: None,
+            ..capture.clone()
+        };
+        let thread_shared = Arc::clone(&shared);
+        thread::spawn(move || {
+            let windows = Window::all().unwrap_or_default();
+            let mut source = match screen_source(&capture, windows.iter().find(game_exist)) {
+                Ok(source) => source,
+                Err(err) => return thread_shared.publish(Err(err.to_string()), true),
+            };
+
+            while !thread_shared.stop.load(Ordering::Relaxed) {
+                let frame = source.grab().map_err(|err| {
+                    log::warn!("background capture failed: {}", err);
+                    err.to_string()
+                });
+                thread_shared.publish(frame, false);
+                thread::sleep(interval);
+            }
+        });
+
+        Self {
+            shared,
+            last_seq: 0,
+        }
+    }
+}
+
+impl ScreenSource for BackgroundSource {
+    fn grab(&mut self) -> Result<DynamicImage, EncounterError> {
+        let mut latest = self.shared.latest.lock().map_err(EncounterError::capture)?;
+        while latest.seq == self.last_seq && !latest.finished {
+            latest = self
+                .shared
+                .ready
+                .wait(latest)
+                .map_err(EncounterError::capture)?;
+        }
+
+        self.last_seq = latest.seq;
+        latest.frame.clone().map_err(EncounterError::CaptureFailed)
+    }
+}
+
+impl Drop for BackgroundSource {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Requests frames from a capture thread and receives them.
+struct CaptureWorker {
+    requests: Sender<()>,
+    frames: Receiver<Result<DynamicImage, EncounterError>>,
+}
+
+impl CaptureWorker {
+    /// Sources can't move between threads, so like `BackgroundSource` the thread looks up the
+    /// game window and creates its own. It captures one frame per request.
+    fn spawn(capture: &CaptureConfig) -> Self {
+        let (requests, pending) = mpsc::channel::<()>();
+        let (frame_sender, frames) = mpsc::channel();
+        let capture = capture.clone();
+        thread::spawn(move || {
+            let windows = match game_windows() {
+                Ok(windows) => windows,
+                Err(err) => {
+                    let _ = frame_sender.send(Err(err));
+                    return;
+                }
+            };
+            let mut source = match screen_source(&capture, windows.iter().find(game_exist)) {
+                Ok(source) => source,
+                Err(err) => {
+                    let _ = frame_sender.send(Err(err));
+                    return;
+                }
+            };
+            while pending.recv().is_ok() {
+                if frame_sender.send(source.grab()).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { requests, frames }
+    }
+}
+
+/// Captures on a thread of its own and gives up on frames that take longer than `timeout`,
+/// so a stuck capture can't hang detection, like `TimedOcr` does for OCR. The stuck thread is
+/// left behind and the next frame gets a new one, which looks up the game window again.
+pub struct TimedSource {
+    capture: CaptureConfig,
+    timeout: Duration,
+    worker: Option<CaptureWorker>,
+}
+
+impl TimedSource {
+    /// Captures from the source `capture` selects, see `screen_source`.
+    pub fn new(capture: &CaptureConfig, timeout: Duration) -> Self {
+        Self {
+            capture: capture.clone(),
+            timeout,
+            worker: None,
+        }
+    }
+}
+
+impl ScreenSource for TimedSource {
+    fn grab(&mut self) -> Result<DynamicImage, EncounterError> {
+        let worker = self
+            .worker
+            .get_or_insert_with(|| CaptureWorker::spawn(&self.capture));
+        // A thread that failed to create its source already sent why and stopped.
+        let _ = worker.requests.send(());
+        match worker.frames.recv_timeout(self.timeout) {
+            Ok(frame) => frame,
+            Err(RecvTimeoutError::Timeout) => {
+                self.worker = None;
+                Err(EncounterError::CaptureTimedOut(self.timeout))
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                self.worker = None;
+                Err(EncounterError::capture("capture thread stopped"))
+            }
+        }
+    }
+}
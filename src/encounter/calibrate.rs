@@ -0,0 +1,52 @@
+use image::DynamicImage;
+use ocrs::OcrEngine;
+
+use super::{trace_ocr, CaptureRegion, EncounterError, TextBox};
+
+/// Suggests a capture region for `image`, a screenshot of a battle: the box around every line
+/// with one of `level_markers`, padded by the height of the tallest such line on every side
+/// and kept inside the image. `None` when no level line was read.
+pub fn calibrate(
+    engine: &OcrEngine,
+    image: &DynamicImage,
+    level_markers: &[String],
+    line_padding: u32,
+) -> Result<Option<CaptureRegion>, EncounterError> {
+    let trace = trace_ocr(engine, image, line_padding)?;
+    let level_lines = trace
+        .lines
+        .iter()
+        .filter(|(text, _)| {
+            text.split_whitespace().any(|word| {
+                level_markers
+                    .iter()
+                    .any(|marker| word.to_lowercase() == marker.to_lowercase())
+            })
+        })
+        .map(|(_, rect)| *rect)
+        .collect::<Vec<_>>();
+
+    let Some(bounds) = level_lines.iter().copied().reduce(TextBox::union) else {
+        return Ok(None);
+    };
+    let padding = level_lines
+        .iter()
+        .map(|rect| rect.bottom - rect.top)
+        .max()
+        .unwrap_or(0);
+
+    let left = (bounds.left - padding).max(0);
+    let top = (bounds.top - padding).max(0);
+    let right = (bounds.right + padding).min(image.width() as i32);
+    let bottom = (bounds.bottom + padding).min(image.height() as i32);
+    if right <= left || bottom <= top {
+        return Ok(None);
+    }
+
+    Ok(Some(CaptureRegion {
+        x: left as u32,
+        y: top as u32,
+        width: (right - left) as u32,
+        height: (bottom - top) as u32,
+    }))
+}
@@ -0,0 +1,577 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::{EncounterError, Hotkeys};
+
+const ENCOUNTER_DETECT_FRAMES: u32 = 2;
+const END_ENCOUNTER_CYCLES: u32 = 1;
+const NEW_SPECIES_ENCOUNTERS: u32 = 1;
+const MERGE_DISTANCE: usize = 1;
+const VOTE_SHARE: f32 = 0.5;
+const CAPTURE_RETRIES: u32 = 3;
+const CAPTURE_RETRY_BACKOFF_MS: u64 = 200;
+const LEVEL_MARKERS: [&str; 3] = ["lv.", "nv.", "niv."];
+const BANNED_WORDS: [&str; 3] = ["lv.", "llv.", "alpha"];
+
+/// Everything that can be set in a config file, see `load_config`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    #[serde(flatten)]
+    pub detection: DetectionConfig,
+    /// Encounters wanted per species, e.g. for a living dex. See `EncounterState::remaining_targets`.
+    pub targets: HashMap<String, u32>,
+    pub hotkeys: Hotkeys,
+    /// Settings per game language, by a name of your choice. See `LanguageConfig`.
+    pub languages: HashMap<String, LanguageConfig>,
+    /// Key of `languages` to start with.
+    pub language: Option<String>,
+    /// Encounters in the feed of the counter and on `/recent`, `--recent` on the command line.
+    pub recent_encounters: Option<usize>,
+}
+
+/// What changes with the language of the game. Selecting a language replaces the
+/// recognition model, level markers and battle tokens with these.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LanguageConfig {
+    /// Recognition model trained for the language. The default model is looked up when `None`.
+    pub recognition_model: Option<PathBuf>,
+    pub level_markers: Vec<String>,
+    pub battle_tokens: Vec<String>,
+}
+
+impl Default for LanguageConfig {
+    fn default() -> Self {
+        Self {
+            recognition_model: None,
+            level_markers: LEVEL_MARKERS.iter().map(|m| m.to_string()).collect(),
+            battle_tokens: vec![],
+        }
+    }
+}
+
+/// Reads a TOML config file, keys missing from it keep their default.
+pub fn load_config(path: &Path) -> Result<Config, Box<dyn Error>> {
+    let config = fs::read_to_string(path)?;
+    Ok(toml::from_str(&config)?)
+}
+
+/// Tunables for the detection loop in `encounter_process`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DetectionConfig {
+    pub mode: DetectionMode,
+    pub capture: CaptureConfig,
+    pub preprocess: PreprocessConfig,
+    /// Delay after each captured frame. Falls back to the delay of the selected game mode when `None`.
+    pub sleep_ms: Option<u64>,
+    /// Frames read per second at most. Each frame then waits for what is left of its
+    /// `1 / max_fps` second after capture and OCR, in place of `sleep_ms` and the game mode delay.
+    pub max_fps: Option<f64>,
+    /// Frames captured per cycle before deciding on a mode change, at least one.
+    pub detect_frames: u32,
+    /// Minimum time after an encounter before the next one is counted.
+    pub encounter_cooldown_ms: u64,
+    /// Cycles in a row without any mon read before a battle counts as over. Higher values
+    /// keep a battle going through frames where OCR misses the names, e.g. during animations.
+    pub end_encounter_cycles: u32,
+    /// Encounters a name never counted before must be read in before it becomes a species in
+    /// `mon_stats`, so a one-off misread doesn't. 1 counts every new name right away.
+    pub new_species_encounters: u32,
+    /// Mons a battle must show to be counted, e.g. 2 for double battles, so a cycle that read
+    /// only one of the two names waits for a better read. Two of the same species count as two.
+    /// Any number does when `None`.
+    pub expected_mons_per_encounter: Option<u32>,
+    /// Pixels every detected text line is grown by on each side before it is recognized,
+    /// for small fonts whose tops and bottoms the line boxes cut off. 0 keeps the boxes as found.
+    pub line_padding: u32,
+    /// Encounters whose mons were read on fewer frames than this are tagged brief. 0 tags none.
+    pub brief_encounter_frames: u32,
+    /// Capture or text recognition of a frame that takes longer than this is given up on and
+    /// the cycle skipped, see `TimedSource` and `TimedOcr`. No limit when `None`.
+    pub frame_timeout_ms: Option<u64>,
+    /// Log how long capture and every OCR stage took, summed per cycle.
+    pub log_timings: bool,
+    /// Frames that differ by at most this many percent from the last recognized frame reuse
+    /// its reading instead of running OCR again. 0 runs OCR on every frame.
+    pub static_frame_threshold: f32,
+    /// Names read on different frames of a cycle that are at most this many edits apart are the same mon.
+    pub merge_distance: usize,
+    /// Share of the frames that read anything a mon must be read on to be counted,
+    /// 1.0 only counts mons read on every such frame.
+    pub vote_share: f32,
+    /// Words marking the level on a mon line, the name is the word right before one. Matched ignoring case.
+    pub level_markers: Vec<String>,
+    /// Rewrites of systematic OCR errors, applied in order to every candidate name before
+    /// the length, banned word and dictionary checks.
+    pub normalize: Vec<NormalizeRule>,
+    /// Lines matching one of these are read through it instead of looking for level markers.
+    pub name_patterns: Vec<NamePattern>,
+    /// Detected names containing any of these, ignoring case, are not counted.
+    pub banned_words: Vec<String>,
+    /// Whole names that are never counted, lowercased. Matched after `normalize` and against
+    /// the species a name is snapped to, for labels that pass every other check.
+    pub ignore_exact: HashSet<String>,
+    /// Names counted as another species, matched ignoring case after `normalize` and the
+    /// dictionary, e.g. "raicnu" to "Raichu" or "alolan raichu" to "Raichu".
+    pub aliases: HashMap<String, String>,
+    /// Whether aliases that only put words in front of the species, like regional forms,
+    /// apply. When false such forms are counted as species of their own.
+    pub merge_aliases: bool,
+    /// Text of the battle UI, one of these must be on a frame, ignoring case, for its mons
+    /// to start an encounter. Any frame does when empty.
+    pub battle_tokens: Vec<String>,
+    /// Text of the title screen or another cue of a soft reset, ignoring case. A frame with
+    /// one of these counts a reset, once until the cue is gone again. None detected when empty.
+    pub reset_tokens: Vec<String>,
+    /// JSON Lines file every committed encounter is appended to.
+    pub event_log: Option<PathBuf>,
+    /// Folder the preprocessed regions of the cycle that counted an encounter are saved to,
+    /// to check what a miscount was read from.
+    pub save_encounter_frames: Option<PathBuf>,
+    /// Minimum time between two writes of a changed state. 0 writes every change right away,
+    /// unchanged states are never written.
+    pub save_interval_ms: u64,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        Self {
+            mode: DetectionMode::Pokemon,
+            capture: CaptureConfig::default(),
+            preprocess: PreprocessConfig::default(),
+            sleep_ms: None,
+            max_fps: None,
+            detect_frames: ENCOUNTER_DETECT_FRAMES,
+            encounter_cooldown_ms: 0,
+            end_encounter_cycles: END_ENCOUNTER_CYCLES,
+            new_species_encounters: NEW_SPECIES_ENCOUNTERS,
+            expected_mons_per_encounter: None,
+            line_padding: 0,
+            brief_encounter_frames: 0,
+            frame_timeout_ms: None,
+            log_timings: false,
+            static_frame_threshold: 0.0,
+            merge_distance: MERGE_DISTANCE,
+            vote_share: VOTE_SHARE,
+            level_markers: LEVEL_MARKERS.iter().map(|m| m.to_string()).collect(),
+            normalize: vec![],
+            name_patterns: vec![],
+            banned_words: BANNED_WORDS.iter().map(|w| w.to_string()).collect(),
+            ignore_exact: HashSet::new(),
+            aliases: HashMap::new(),
+            merge_aliases: true,
+            battle_tokens: vec![],
+            reset_tokens: vec![],
+            event_log: None,
+            save_encounter_frames: None,
+            save_interval_ms: 0,
+        }
+    }
+}
+
+impl DetectionConfig {
+    /// Stops counting `name` from the next cycle on, see `ignore_exact`.
+    pub fn ignore(&mut self, name: &str) {
+        self.ignore_exact.insert(name.trim().to_lowercase());
+    }
+
+    /// Parses `name=species` and counts `name` as `species` from then on, see `aliases`.
+    pub fn alias(&mut self, s: &str) -> Result<(), String> {
+        let (name, species) = s
+            .split_once('=')
+            .ok_or_else(|| format!("invalid alias '{}': expected name=species", s))?;
+        self.aliases
+            .insert(name.trim().to_lowercase(), species.trim().to_string());
+        Ok(())
+    }
+}
+
+/// What the detection loop counts.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DetectionMode {
+    /// Mon names in front of a level marker, see `DefaultMonFilter`.
+    #[default]
+    Pokemon,
+    /// Any text: on lines matching `trigger`, every match of `extract` is counted, its first
+    /// group when it has one. Both see the lowercased OCR line. The level markers, banned
+    /// words, battle tokens and species dictionary are not used.
+    Generic {
+        #[serde(with = "regex_string")]
+        trigger: Regex,
+        #[serde(with = "regex_string")]
+        extract: Regex,
+    },
+    /// Egg hatches: on lines matching `trigger`, the species `extract` picks out like for
+    /// `Generic` is counted as an encounter and an egg hatched. The name goes through the same
+    /// normalize, banned word, alias and dictionary checks as mon names.
+    Hatch {
+        #[serde(with = "regex_string")]
+        trigger: Regex,
+        #[serde(with = "regex_string")]
+        extract: Regex,
+    },
+}
+
+impl DetectionMode {
+    /// Parses `trigger=extract`, split at the first `=`.
+    pub fn parse_generic(s: &str) -> Result<Self, String> {
+        let (trigger, extract) = parse_trigger_extract(s)?;
+        Ok(DetectionMode::Generic { trigger, extract })
+    }
+
+    /// Parses `trigger=extract` for `Hatch`, like `parse_generic`.
+    pub fn parse_hatch(s: &str) -> Result<Self, String> {
+        let (trigger, extract) = parse_trigger_extract(s)?;
+        Ok(DetectionMode::Hatch { trigger, extract })
+    }
+}
+
+fn parse_trigger_extract(s: &str) -> Result<(Regex, Regex), String> {
+    let (trigger, extract) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid text mode '{}': expected trigger=extract", s))?;
+    let regex =
+        |pattern| Regex::new(pattern).map_err(|e| format!("invalid text mode '{}': {}", s, e));
+    Ok((regex(trigger)?, regex(extract)?))
+}
+
+impl PartialEq for DetectionMode {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (DetectionMode::Pokemon, DetectionMode::Pokemon) => true,
+            (
+                DetectionMode::Generic { trigger, extract },
+                DetectionMode::Generic {
+                    trigger: other_trigger,
+                    extract: other_extract,
+                },
+            )
+            | (
+                DetectionMode::Hatch { trigger, extract },
+                DetectionMode::Hatch {
+                    trigger: other_trigger,
+                    extract: other_extract,
+                },
+            ) => {
+                trigger.as_str() == other_trigger.as_str()
+                    && extract.as_str() == other_extract.as_str()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Regexes as their pattern string.
+mod regex_string {
+    use regex::Regex;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(regex.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern).map_err(de::Error::custom)
+    }
+}
+
+/// Image adjustments applied to the cropped frame, in field order, before OCR.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreprocessConfig {
+    /// Resize factor for the cropped frame, e.g. 0.5 halves both sides. OCR takes roughly
+    /// the square of it in time, small text gets harder to read. 1.0 keeps the full resolution.
+    pub scale: f32,
+    /// Added to every channel, negative values darken the frame.
+    pub brightness: i32,
+    /// Contrast change in percent, negative values reduce it.
+    pub contrast: f32,
+    pub grayscale: bool,
+    /// Binarization after the other steps, the frame is then always grayscale.
+    pub threshold: ThresholdMode,
+}
+
+impl Default for PreprocessConfig {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            brightness: 0,
+            contrast: 0.0,
+            grayscale: true,
+            threshold: ThresholdMode::None,
+        }
+    }
+}
+
+/// How a frame is turned into black text on a white background before OCR.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThresholdMode {
+    /// Keep the shades of the frame.
+    #[default]
+    None,
+    /// Pick the level per frame by Otsu's method.
+    Otsu,
+    /// Pixels brighter than this level are one color, the rest the other.
+    Fixed(u8),
+}
+
+impl FromStr for ThresholdMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "none" => Ok(ThresholdMode::None),
+            "otsu" => Ok(ThresholdMode::Otsu),
+            level => level.parse().map(ThresholdMode::Fixed).map_err(|_| {
+                format!(
+                    "invalid threshold '{}': expected none, otsu or a level from 0 to 255",
+                    s
+                )
+            }),
+        }
+    }
+}
+
+/// Where frames for the OCR engine come from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureConfig {
+    /// Crops of the captured frame that are read, e.g. the mon names and the battle menu.
+    /// The upper half when empty.
+    pub regions: Vec<CaptureRegion>,
+    /// Index into `list_displays()`. When set the whole display is captured instead of the game window.
+    pub display: Option<usize>,
+    /// Captures the first window with this in its title, ignoring case, instead of the game window.
+    pub window_title: Option<String>,
+    /// Extra attempts after a failed capture before the error is returned.
+    pub retries: u32,
+    /// Wait before the first retry, doubled on every following one.
+    pub retry_backoff_ms: u64,
+    /// When set, frames are captured on a background thread this often and detection
+    /// takes the latest one.
+    pub background_interval_ms: Option<u64>,
+    /// Byte order of the captured pixels, for capture setups that hand over swapped channels.
+    pub pixel_order: PixelOrder,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            regions: vec![],
+            display: None,
+            window_title: None,
+            retries: CAPTURE_RETRIES,
+            retry_backoff_ms: CAPTURE_RETRY_BACKOFF_MS,
+            background_interval_ms: None,
+            pixel_order: PixelOrder::Rgba,
+        }
+    }
+}
+
+/// Order of the four bytes of a captured pixel. Frames are read as RGBA, the other orders
+/// are rearranged into it before cropping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PixelOrder {
+    #[default]
+    Rgba,
+    Bgra,
+    Argb,
+    Abgr,
+}
+
+impl PixelOrder {
+    /// Index of the red, green, blue and alpha byte within a pixel.
+    pub fn rgba_indices(self) -> [usize; 4] {
+        match self {
+            PixelOrder::Rgba => [0, 1, 2, 3],
+            PixelOrder::Bgra => [2, 1, 0, 3],
+            PixelOrder::Argb => [1, 2, 3, 0],
+            PixelOrder::Abgr => [3, 2, 1, 0],
+        }
+    }
+}
+
+impl FromStr for PixelOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "rgba" => Ok(PixelOrder::Rgba),
+            "bgra" => Ok(PixelOrder::Bgra),
+            "argb" => Ok(PixelOrder::Argb),
+            "abgr" => Ok(PixelOrder::Abgr),
+            _ => Err(format!(
+                "invalid pixel order '{}': expected rgba, bgra, argb or abgr",
+                s
+            )),
+        }
+    }
+}
+
+/// Part of the game window that is handed to the OCR engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CaptureRegion {
+    /// Fails when the region is empty or does not fit inside a `frame_width` x `frame_height` frame.
+    pub fn check_bounds(&self, frame_width: u32, frame_height: u32) -> Result<(), EncounterError> {
+        let fits_x = self
+            .x
+            .checked_add(self.width)
+            .is_some_and(|r| r <= frame_width);
+        let fits_y = self
+            .y
+            .checked_add(self.height)
+            .is_some_and(|b| b <= frame_height);
+
+        if self.width == 0 || self.height == 0 || !fits_x || !fits_y {
+            return Err(EncounterError::InvalidRegion(format!(
+                "capture region {}x{} at ({}, {}) is outside the {}x{} frame",
+                self.width, self.height, self.x, self.y, frame_width, frame_height
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Parses `x,y,width,height`, e.g. `0,0,1920,540`.
+impl FromStr for CaptureRegion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid capture region '{}': {}", s, e))?;
+
+        match values[..] {
+            [x, y, width, height] => Ok(Self {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err(format!(
+                "invalid capture region '{}': expected x,y,width,height",
+                s
+            )),
+        }
+    }
+}
+
+/// Replaces every match of `pattern` in a name with `replacement`, which may refer to groups as `$1`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "(String, String)", into = "(String, String)")]
+pub struct NormalizeRule {
+    pub pattern: Regex,
+    pub replacement: String,
+}
+
+impl NormalizeRule {
+    pub fn apply(&self, name: &str) -> String {
+        self.pattern
+            .replace_all(name, self.replacement.as_str())
+            .into_owned()
+    }
+}
+
+impl PartialEq for NormalizeRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern.as_str() == other.pattern.as_str() && self.replacement == other.replacement
+    }
+}
+
+impl TryFrom<(String, String)> for NormalizeRule {
+    type Error = regex::Error;
+
+    fn try_from((pattern, replacement): (String, String)) -> Result<Self, Self::Error> {
+        Ok(Self {
+            pattern: Regex::new(&pattern)?,
+            replacement,
+        })
+    }
+}
+
+impl From<NormalizeRule> for (String, String) {
+    fn from(rule: NormalizeRule) -> Self {
+        (rule.pattern.as_str().to_string(), rule.replacement)
+    }
+}
+
+impl FromStr for NormalizeRule {
+    type Err = String;
+
+    /// Parses `pattern=replacement`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, replacement) = s
+            .split_once('=')
+            .ok_or_else(|| format!("invalid rule '{}': expected pattern=replacement", s))?;
+        Self::try_from((pattern.to_string(), replacement.to_string()))
+            .map_err(|e| format!("invalid rule '{}': {}", s, e))
+    }
+}
+
+/// Where the name sits on a line without a level marker, e.g. `wild (.+?) appeared` for
+/// "Wild Gyarados appeared!". The name is the first group, the whole match without one.
+/// Matched against the lowercased line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct NamePattern(pub Regex);
+
+impl NamePattern {
+    /// Every name on `line`, `None` when the pattern doesn't match it.
+    pub fn names<'l>(&self, line: &'l str) -> Option<Vec<&'l str>> {
+        let names = self
+            .0
+            .captures_iter(line)
+            .filter_map(|captures| captures.get(1).or(captures.get(0)))
+            .map(|name| name.as_str().trim())
+            .collect::<Vec<_>>();
+        (!names.is_empty()).then_some(names)
+    }
+}
+
+impl PartialEq for NamePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl TryFrom<String> for NamePattern {
+    type Error = regex::Error;
+
+    fn try_from(pattern: String) -> Result<Self, Self::Error> {
+        Regex::new(&pattern).map(Self)
+    }
+}
+
+impl From<NamePattern> for String {
+    fn from(pattern: NamePattern) -> Self {
+        pattern.0.as_str().to_string()
+    }
+}
+
+impl FromStr for NamePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(s.to_string()).map_err(|e| format!("invalid name pattern '{}': {}", s, e))
+    }
+}
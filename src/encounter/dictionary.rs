@@ -0,0 +1,63 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+pub const DEFAULT_MAX_DISTANCE: usize = 2;
+
+/// List of known species that OCR output is snapped to.
+#[derive(Debug, Clone, Default)]
+pub struct SpeciesDictionary {
+    /// Lowercased for matching, with the spelling of the species file.
+    species: Vec<(String, String)>,
+    max_distance: usize,
+}
+
+impl SpeciesDictionary {
+    pub fn new(species: Vec<String>, max_distance: usize) -> Self {
+        Self {
+            species: species
+                .iter()
+                .map(|s| (s.trim().to_lowercase(), s.trim().to_string()))
+                .collect(),
+            max_distance,
+        }
+    }
+
+    /// Reads one species per line, empty lines are skipped.
+    pub fn load(path: &Path, max_distance: usize) -> Result<Self, Box<dyn Error>> {
+        let species = fs::read_to_string(path)?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.to_string())
+            .collect();
+        Ok(Self::new(species, max_distance))
+    }
+
+    /// Returns the closest species to `name`, spelled as in the species file, or `None` when
+    /// every species is more than `max_distance` edits away. Case is ignored.
+    pub fn snap(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.species
+            .iter()
+            .map(|(matched, species)| (species, levenshtein(&name, matched)))
+            .filter(|(_, distance)| *distance <= self.max_distance)
+            .min_by_key(|(_, distance)| *distance)
+            .map(|(species, _)| species.as_str())
+    }
+}
+
+pub(super) fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+/// Everything that can go wrong while capturing, recognizing or persisting encounters.
+#[derive(Debug)]
+pub enum EncounterError {
+    DisplayNotFound {
+        index: usize,
+        available: usize,
+    },
+    InvalidRegion(String),
+    /// Windows or displays can't be listed, e.g. on a headless machine without a display server.
+    NoDisplay(String),
+    WindowNotFound(String),
+    CaptureFailed(String),
+    /// Capturing a frame didn't finish within the frame timeout, see `TimedSource`.
+    CaptureTimedOut(Duration),
+    Ocr(String),
+    /// Text recognition of a frame didn't finish within the frame timeout.
+    OcrTimedOut(Duration),
+    Image(image::ImageError),
+    Io(io::Error),
+    Serde(serde_json::Error),
+    #[cfg(feature = "sqlite")]
+    Sqlite(rusqlite::Error),
+    /// The state file was written by a newer version of the counter.
+    UnsupportedVersion(u32),
+}
+
+impl EncounterError {
+    pub(crate) fn capture(err: impl fmt::Display) -> Self {
+        EncounterError::CaptureFailed(err.to_string())
+    }
+
+    pub(crate) fn no_display(err: impl fmt::Display) -> Self {
+        EncounterError::NoDisplay(err.to_string())
+    }
+
+    pub(crate) fn ocr(err: impl fmt::Display) -> Self {
+        EncounterError::Ocr(err.to_string())
+    }
+
+    /// Whether loading failed only because nothing was saved yet.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, EncounterError::Io(err) if err.kind() == io::ErrorKind::NotFound)
+    }
+}
+
+impl fmt::Display for EncounterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncounterError::DisplayNotFound { index, available } => write!(
+                f,
+                "display {} not found, {} displays available",
+                index, available
+            ),
+            EncounterError::InvalidRegion(reason) => write!(f, "{}", reason),
+            EncounterError::NoDisplay(reason) => {
+                write!(f, "no display to capture from: {}", reason)
+            }
+            EncounterError::WindowNotFound(title) => {
+                write!(f, "no window with \"{}\" in its title", title)
+            }
+            EncounterError::CaptureFailed(reason) => write!(f, "screen capture failed: {}", reason),
+            EncounterError::CaptureTimedOut(timeout) => {
+                write!(f, "screen capture took longer than {:?}", timeout)
+            }
+            EncounterError::Ocr(reason) => write!(f, "text recognition failed: {}", reason),
+            EncounterError::OcrTimedOut(timeout) => {
+                write!(f, "text recognition took longer than {:?}", timeout)
+            }
+            EncounterError::Image(err) => write!(f, "image error: {}", err),
+            EncounterError::Io(err) => write!(f, "io error: {}", err),
+            EncounterError::Serde(err) => write!(f, "state file error: {}", err),
+            #[cfg(feature = "sqlite")]
+            EncounterError::Sqlite(err) => write!(f, "state database error: {}", err),
+            EncounterError::UnsupportedVersion(version) => {
+                write!(f, "state file version {} is newer than supported", version)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EncounterError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EncounterError::Image(err) => Some(err),
+            EncounterError::Io(err) => Some(err),
+            EncounterError::Serde(err) => Some(err),
+            #[cfg(feature = "sqlite")]
+            EncounterError::Sqlite(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<image::ImageError> for EncounterError {
+    fn from(err: image::ImageError) -> Self {
+        EncounterError::Image(err)
+    }
+}
+
+impl From<io::Error> for EncounterError {
+    fn from(err: io::Error) -> Self {
+        EncounterError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for EncounterError {
+    fn from(err: serde_json::Error) -> Self {
+        EncounterError::Serde(err)
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for EncounterError {
+    fn from(err: rusqlite::Error) -> Self {
+        EncounterError::Sqlite(err)
+    }
+}
@@ -0,0 +1,131 @@
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use super::{EncounterError, EncounterRecord, EncounterState};
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+impl EncounterState {
+    /// Writes `mon_stats` as a `species,count` CSV, most encountered first.
+    pub fn export_csv(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.write_stats_csv(path, false)
+    }
+
+    /// Like `export_csv`, with the `first_seen,last_seen` encounter numbers after the count.
+    pub fn export_seen_csv(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.write_stats_csv(path, true)
+    }
+
+    fn write_stats_csv(&self, path: &Path, seen: bool) -> Result<(), Box<dyn Error>> {
+        let mut stats = self.mon_stats.iter().collect::<Vec<_>>();
+        stats.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(b.0)));
+
+        let mut csv = String::from(if seen {
+            "species,count,first_seen,last_seen\n"
+        } else {
+            "species,count\n"
+        });
+        for (species, stat) in stats {
+            csv.push_str(&format!("{},{}", csv_field(species), stat.count));
+            if seen {
+                csv.push_str(&format!(",{},{}", stat.first_seen, stat.last_seen));
+            }
+            csv.push('\n');
+        }
+
+        fs::write(path, csv)?;
+        Ok(())
+    }
+
+    /// Writes `gap_histogram` of `species` as a `gap,count` CSV, every gap up to the longest.
+    pub fn export_gap_csv(&self, species: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut csv = String::from("gap,count\n");
+        for (gap, count) in self.gap_histogram(species).iter().enumerate() {
+            csv.push_str(&format!("{},{}\n", gap, count));
+        }
+
+        fs::write(path, csv)?;
+        Ok(())
+    }
+}
+
+/// Writes the whole `state` to stdout as JSON, indented when `pretty`, to pipe it into e.g. `jq`.
+pub fn print_state(state: &EncounterState, pretty: bool) -> Result<(), EncounterError> {
+    let json = if pretty {
+        serde_json::to_string_pretty(state)?
+    } else {
+        serde_json::to_string(state)?
+    };
+    println!("{}", json);
+    Ok(())
+}
+
+impl EncounterRecord {
+    /// Appends the record as one line of JSON to `path`, creating the file when needed.
+    pub(super) fn append_to(&self, path: &Path) -> Result<(), EncounterError> {
+        let mut line = serde_json::to_string(self)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_with_commas_or_quotes_are_quoted() {
+        assert_eq!(csv_field("Mr. Mime"), "Mr. Mime");
+        assert_eq!(csv_field("Nidoran, F"), "\"Nidoran, F\"");
+        assert_eq!(csv_field("Farfetch\"d"), "\"Farfetch\"\"d\"");
+    }
+
+    #[test]
+    fn stats_csv_is_sorted_by_count() {
+        let mut state = EncounterState::default();
+        for mon in ["Nidoran, F", "Abra", "Abra"] {
+            state.count_battle(vec![mon.to_string()], vec![], 1, 1);
+        }
+        let path = std::env::temp_dir().join("rencounter-export-stats.csv");
+        state.export_seen_csv(&path).unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            csv,
+            "species,count,first_seen,last_seen\nAbra,2,2,3\n\"Nidoran, F\",1,1,1\n"
+        );
+    }
+
+    #[test]
+    fn gap_histogram_counts_every_gap_up_to_the_longest() {
+        let mut state = EncounterState::default();
+        for mon in [
+            "Abra", "Zubat", "abra", "Abra", "Zubat", "Zubat", "Zubat", "Abra",
+        ] {
+            state.count_battle(vec![mon.to_string()], vec![], 1, 1);
+        }
+        // Abra at encounters 1, 3, 4 and 8.
+        assert_eq!(state.gap_histogram("ABRA"), [0, 1, 1, 0, 1]);
+        assert!(state.gap_histogram("Mew").is_empty());
+
+        let path = std::env::temp_dir().join("rencounter-export-gaps.csv");
+        state.export_gap_csv("Abra", &path).unwrap();
+        let csv = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(csv, "gap,count\n0,0\n1,1\n2,1\n3,0\n4,1\n");
+    }
+}
@@ -0,0 +1,427 @@
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+
+use super::{DetectionConfig, DetectionMode, NamePattern, NormalizeRule, SpeciesDictionary};
+
+/// Why a recognized word was not counted as a mon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiscardReason {
+    NoLevelMarker,
+    NotBeforeLevelMarker,
+    TooShort,
+    Banned,
+    Ignored,
+    NotInDictionary,
+    /// Rejected by a custom `MonFilter`.
+    Custom(String),
+}
+
+impl std::fmt::Display for DiscardReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiscardReason::NoLevelMarker => write!(f, "line has no level marker"),
+            DiscardReason::NotBeforeLevelMarker => write!(f, "not followed by a level marker"),
+            DiscardReason::TooShort => write!(f, "too short"),
+            DiscardReason::Banned => write!(f, "banned word"),
+            DiscardReason::Ignored => write!(f, "on the ignore list"),
+            DiscardReason::NotInDictionary => write!(f, "no close species in the dictionary"),
+            DiscardReason::Custom(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetectedWord {
+    /// Raw OCR text.
+    pub text: String,
+    /// What gets counted, the closest dictionary species when a dictionary is used.
+    pub name: String,
+    /// `None` when the word would be counted as a mon.
+    pub discarded: Option<DiscardReason>,
+}
+
+/// Decides which words of a recognized line are mon names.
+pub trait MonFilter {
+    /// Classifies every word of a lowercased OCR line.
+    fn classify(&self, line: &str) -> Vec<DetectedWord>;
+
+    /// Whether a lowercased OCR line shows the frame is a battle. A walk only turns into
+    /// an encounter on frames with such a line.
+    fn confirms_battle(&self, _line: &str) -> bool {
+        true
+    }
+
+    /// Whether a lowercased OCR line shows the game was soft reset, e.g. its title screen.
+    fn shows_reset(&self, _line: &str) -> bool {
+        false
+    }
+
+    /// The name OCR text `text` of a counted mon stands for under the current rules, `None`
+    /// when it would no longer be counted. See `EncounterState::recompute_stats`.
+    fn recheck(&self, text: &str) -> Option<String> {
+        Some(text.to_string())
+    }
+
+    /// The mons of an encounter about to be counted, as voted over the cycle. The returned
+    /// mons are counted instead, none rejects the encounter.
+    fn review(&self, mons: Vec<String>) -> Vec<String> {
+        mons
+    }
+}
+
+/// Hands every encounter to `on_detect` before it is counted, e.g. to confirm it by hand,
+/// see `MonFilter::review`. Words are classified by `inner`.
+pub struct ReviewedFilter<'a> {
+    inner: Box<dyn MonFilter + 'a>,
+    on_detect: Box<dyn Fn(&[String]) -> Vec<String> + 'a>,
+}
+
+impl<'a> ReviewedFilter<'a> {
+    pub fn new(
+        inner: Box<dyn MonFilter + 'a>,
+        on_detect: Box<dyn Fn(&[String]) -> Vec<String> + 'a>,
+    ) -> Self {
+        Self { inner, on_detect }
+    }
+}
+
+impl MonFilter for ReviewedFilter<'_> {
+    fn classify(&self, line: &str) -> Vec<DetectedWord> {
+        self.inner.classify(line)
+    }
+
+    fn confirms_battle(&self, line: &str) -> bool {
+        self.inner.confirms_battle(line)
+    }
+
+    fn shows_reset(&self, line: &str) -> bool {
+        self.inner.shows_reset(line)
+    }
+
+    fn recheck(&self, text: &str) -> Option<String> {
+        self.inner.recheck(text)
+    }
+
+    fn review(&self, mons: Vec<String>) -> Vec<String> {
+        (self.on_detect)(&self.inner.review(mons))
+    }
+}
+
+/// The filter for `config.mode`, the dictionary only applies to Pokémon mode.
+pub fn mode_filter<'a>(
+    config: &'a DetectionConfig,
+    dictionary: Option<&'a SpeciesDictionary>,
+) -> Box<dyn MonFilter + 'a> {
+    match &config.mode {
+        DetectionMode::Pokemon => {
+            Box::new(DefaultMonFilter::new(config).with_dictionary(dictionary))
+        }
+        DetectionMode::Generic { trigger, extract } => Box::new(TextFilter { trigger, extract }),
+        DetectionMode::Hatch { trigger, extract } => Box::new(HatchFilter {
+            text: TextFilter { trigger, extract },
+            names: DefaultMonFilter::new(config).with_dictionary(dictionary),
+        }),
+    }
+}
+
+/// How a mon name is shown and stored: every word starts upper case, so "mr. mime" becomes
+/// "Mr. Mime". Names snapped to the dictionary keep its spelling instead.
+pub(super) fn display_name(name: &str) -> String {
+    let mut display = String::with_capacity(name.len());
+    let mut word_start = true;
+    for c in name.chars() {
+        if word_start {
+            display.extend(c.to_uppercase());
+        } else {
+            display.extend(c.to_lowercase());
+        }
+        word_start = c.is_whitespace();
+    }
+    display
+}
+
+/// Counts a word when it directly precedes a level marker, is longer than one character and
+/// contains none of the banned words. Two-word names like "mr. mime" are kept together. With a
+/// dictionary the word is also snapped to the closest species. Lines matching a name pattern
+/// only count what the pattern picks out, the same checks apply.
+pub struct DefaultMonFilter<'a> {
+    pub level_markers: &'a [String],
+    pub normalize: &'a [NormalizeRule],
+    pub name_patterns: &'a [NamePattern],
+    pub banned_words: &'a [String],
+    pub ignore_exact: &'a HashSet<String>,
+    pub aliases: &'a HashMap<String, String>,
+    pub merge_aliases: bool,
+    pub battle_tokens: &'a [String],
+    pub reset_tokens: &'a [String],
+    pub dictionary: Option<&'a SpeciesDictionary>,
+}
+
+impl<'a> DefaultMonFilter<'a> {
+    pub fn new(config: &'a DetectionConfig) -> Self {
+        Self {
+            level_markers: &config.level_markers,
+            normalize: &config.normalize,
+            name_patterns: &config.name_patterns,
+            banned_words: &config.banned_words,
+            ignore_exact: &config.ignore_exact,
+            aliases: &config.aliases,
+            merge_aliases: config.merge_aliases,
+            battle_tokens: &config.battle_tokens,
+            reset_tokens: &config.reset_tokens,
+            dictionary: None,
+        }
+    }
+
+    pub fn with_dictionary(mut self, dictionary: Option<&'a SpeciesDictionary>) -> Self {
+        self.dictionary = dictionary;
+        self
+    }
+
+    fn is_level_marker(&self, word: &str) -> bool {
+        let word = word.to_lowercase();
+        self.level_markers
+            .iter()
+            .any(|marker| word == marker.to_lowercase())
+    }
+
+    /// Index of the first word of the name that ends with `words[end]`. Names with punctuation,
+    /// like "mr. mime", "type: null" or "mime jr.", span two words, and so does any two-word
+    /// species in the dictionary.
+    fn name_start(&self, words: &[&str], end: usize) -> usize {
+        let Some(previous) = end.checked_sub(1).map(|i| words[i]) else {
+            return end;
+        };
+        if self.is_level_marker(previous) {
+            return end;
+        }
+
+        let joined = format!("{} {}", previous, words[end]);
+        let punctuated =
+            previous.ends_with(['.', ':']) || (words[end].ends_with('.') && words[end].len() <= 3);
+        let in_dictionary = self
+            .dictionary
+            .is_some_and(|dictionary| dictionary.snap(&joined).is_some());
+        if punctuated || in_dictionary {
+            end - 1
+        } else {
+            end
+        }
+    }
+
+    fn is_banned(&self, word: &str) -> bool {
+        let word = word.to_lowercase();
+        self.banned_words
+            .iter()
+            .any(|banned| word.contains(&banned.to_lowercase()))
+    }
+
+    fn is_ignored(&self, name: &str) -> bool {
+        self.ignore_exact.contains(&name.to_lowercase())
+    }
+
+    /// The species `name` is counted as, see `DetectionConfig::aliases`.
+    fn canonical(&self, name: String) -> String {
+        let lowercase = name.to_lowercase();
+        let Some(species) = self
+            .aliases
+            .iter()
+            .find(|(alias, _)| alias.to_lowercase() == lowercase)
+            .map(|(_, species)| species)
+        else {
+            return name;
+        };
+        let is_form = lowercase.ends_with(&format!(" {}", species.to_lowercase()));
+        if is_form && !self.merge_aliases {
+            name
+        } else {
+            species.clone()
+        }
+    }
+
+    /// Normalizes a candidate name, runs the length, banned word, ignore list and dictionary
+    /// checks and applies the aliases.
+    fn check_name(&self, text: &str) -> (String, Option<DiscardReason>) {
+        let name = self
+            .normalize
+            .iter()
+            .fold(text.to_string(), |name, rule| rule.apply(&name));
+        if name.len() <= 1 {
+            (name, Some(DiscardReason::TooShort))
+        } else if self.is_banned(&name) {
+            (name, Some(DiscardReason::Banned))
+        } else if self.is_ignored(&name) {
+            (name, Some(DiscardReason::Ignored))
+        } else if let Some(dictionary) = self.dictionary {
+            match dictionary.snap(&name) {
+                Some(species) if self.is_ignored(species) => {
+                    (species.to_string(), Some(DiscardReason::Ignored))
+                }
+                Some(species) => (self.canonical(species.to_string()), None),
+                None => (name, Some(DiscardReason::NotInDictionary)),
+            }
+        } else {
+            (self.canonical(display_name(&name)), None)
+        }
+    }
+}
+
+impl MonFilter for DefaultMonFilter<'_> {
+    fn classify(&self, line: &str) -> Vec<DetectedWord> {
+        if let Some(names) = self
+            .name_patterns
+            .iter()
+            .find_map(|pattern| pattern.names(line))
+        {
+            return names
+                .into_iter()
+                .map(|text| {
+                    let (name, discarded) = self.check_name(text);
+                    DetectedWord {
+                        text: text.to_string(),
+                        name,
+                        discarded,
+                    }
+                })
+                .collect();
+        }
+
+        let lowercase_line = line.to_lowercase();
+        let has_level_marker = self
+            .level_markers
+            .iter()
+            .any(|marker| lowercase_line.contains(&marker.to_lowercase()));
+        let words = line.split_whitespace().collect::<Vec<_>>();
+
+        words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| {
+                let before_level_marker = words
+                    .get(i + 1)
+                    .is_some_and(|next| self.is_level_marker(next));
+                let text = if before_level_marker {
+                    words[self.name_start(&words, i)..=i].join(" ")
+                } else {
+                    word.to_string()
+                };
+
+                let (name, discarded) = if !has_level_marker {
+                    (text.clone(), Some(DiscardReason::NoLevelMarker))
+                } else if !before_level_marker {
+                    (text.clone(), Some(DiscardReason::NotBeforeLevelMarker))
+                } else {
+                    self.check_name(&text)
+                };
+
+                DetectedWord {
+                    text,
+                    name,
+                    discarded,
+                }
+            })
+            .collect()
+    }
+
+    fn confirms_battle(&self, line: &str) -> bool {
+        let line = line.to_lowercase();
+        self.battle_tokens.is_empty()
+            || self
+                .battle_tokens
+                .iter()
+                .any(|token| line.contains(&token.to_lowercase()))
+    }
+
+    fn recheck(&self, text: &str) -> Option<String> {
+        match self.check_name(&text.to_lowercase()) {
+            (name, None) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn shows_reset(&self, line: &str) -> bool {
+        let line = line.to_lowercase();
+        self.reset_tokens
+            .iter()
+            .any(|token| line.contains(&token.to_lowercase()))
+    }
+}
+
+/// Counts every match of `extract` on lines that match `trigger`, see `DetectionMode::Generic`.
+pub struct TextFilter<'a> {
+    pub trigger: &'a Regex,
+    pub extract: &'a Regex,
+}
+
+impl MonFilter for TextFilter<'_> {
+    fn classify(&self, line: &str) -> Vec<DetectedWord> {
+        if !self.trigger.is_match(line) {
+            return vec![];
+        }
+
+        self.extract
+            .captures_iter(line)
+            .filter_map(|captures| {
+                let text = captures.get(0)?.as_str().to_string();
+                let name = captures.get(1).or(captures.get(0))?.as_str().trim();
+                let discarded = if name.is_empty() {
+                    Some(DiscardReason::TooShort)
+                } else {
+                    None
+                };
+                Some(DetectedWord {
+                    name: name.to_string(),
+                    text,
+                    discarded,
+                })
+            })
+            .collect()
+    }
+
+    fn confirms_battle(&self, line: &str) -> bool {
+        self.trigger.is_match(line)
+    }
+
+    fn recheck(&self, text: &str) -> Option<String> {
+        let captures = self.extract.captures(text)?;
+        let name = captures.get(1).or(captures.get(0))?.as_str().trim();
+        (!name.is_empty()).then(|| name.to_string())
+    }
+}
+
+/// Counts the species hatched from an egg, see `DetectionMode::Hatch`. Names are picked out
+/// like `TextFilter` does and then checked like `DefaultMonFilter` checks mon names.
+pub struct HatchFilter<'a> {
+    pub text: TextFilter<'a>,
+    pub names: DefaultMonFilter<'a>,
+}
+
+impl MonFilter for HatchFilter<'_> {
+    fn classify(&self, line: &str) -> Vec<DetectedWord> {
+        self.text
+            .classify(line)
+            .into_iter()
+            .map(|word| match word.discarded {
+                Some(_) => word,
+                None => {
+                    let (name, discarded) = self.names.check_name(&word.name);
+                    DetectedWord {
+                        name,
+                        discarded,
+                        ..word
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn confirms_battle(&self, line: &str) -> bool {
+        self.text.confirms_battle(line)
+    }
+
+    fn recheck(&self, text: &str) -> Option<String> {
+        self.text
+            .recheck(text)
+            .and_then(|name| self.names.recheck(&name))
+    }
+}
@@ -0,0 +1,187 @@
+use serde::{Deserialize, Serialize};
+
+/// What a hotkey does in the counter.
+#[cfg(feature = "tui")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyAction {
+    Start,
+    Pause,
+    Reset,
+    ManualIncrement,
+    SoftReset,
+    IgnoreLast,
+    ToggleGameMode,
+    Debug,
+    NextLanguage,
+    Quit,
+}
+
+/// Key bound to every action, matched ignoring case. Read from the `[hotkeys]` config table.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hotkeys {
+    pub start: char,
+    pub pause: char,
+    pub reset: char,
+    pub manual_increment: char,
+    /// Counts a soft reset by hand, see `EncounterState::record_reset`.
+    pub soft_reset: char,
+    /// Stops counting the mons of the last encounter for the rest of the session.
+    pub ignore_last: char,
+    pub toggle_game_mode: char,
+    pub debug: char,
+    /// Switches to the next of the configured languages, in name order.
+    pub next_language: char,
+    pub quit: char,
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Self {
+            start: 's',
+            pause: 'p',
+            reset: 'r',
+            manual_increment: 'a',
+            soft_reset: 'x',
+            ignore_last: 'i',
+            toggle_game_mode: 't',
+            debug: 'd',
+            next_language: 'l',
+            quit: 'q',
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+impl Hotkeys {
+    /// Action bound to `key`, the first one in field order when a key is bound twice.
+    pub fn action(&self, key: char) -> Option<HotkeyAction> {
+        [
+            (self.start, HotkeyAction::Start),
+            (self.pause, HotkeyAction::Pause),
+            (self.reset, HotkeyAction::Reset),
+            (self.manual_increment, HotkeyAction::ManualIncrement),
+            (self.soft_reset, HotkeyAction::SoftReset),
+            (self.ignore_last, HotkeyAction::IgnoreLast),
+            (self.toggle_game_mode, HotkeyAction::ToggleGameMode),
+            (self.debug, HotkeyAction::Debug),
+            (self.next_language, HotkeyAction::NextLanguage),
+            (self.quit, HotkeyAction::Quit),
+        ]
+        .into_iter()
+        .find(|(bound, _)| bound.to_lowercase().eq(key.to_lowercase()))
+        .map(|(_, action)| action)
+    }
+}
+
+#[cfg(feature = "global-hotkeys")]
+pub use global::GlobalHotkeys;
+
+#[cfg(feature = "global-hotkeys")]
+mod global {
+    use std::mem;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use rdev::{EventType, Key};
+
+    use super::{HotkeyAction, Hotkeys};
+
+    /// Listens for the hotkeys system wide, so the counter can be controlled while the game
+    /// has focus. Keys only count while Ctrl and Shift are held, so typing in the game chat or
+    /// another window never resets or quits the hunt. Pressed actions queue up until `drain`
+    /// is called.
+    #[derive(Debug, Clone)]
+    pub struct GlobalHotkeys {
+        pending: Arc<Mutex<Vec<HotkeyAction>>>,
+    }
+
+    impl GlobalHotkeys {
+        /// Starts listening on a background thread for the rest of the process.
+        pub fn start(hotkeys: Hotkeys) -> Self {
+            let pending = Arc::new(Mutex::new(vec![]));
+
+            let shared = Arc::clone(&pending);
+            thread::spawn(move || {
+                let (mut ctrl, mut shift) = (false, false);
+                let listened = rdev::listen(move |event| {
+                    let (key, pressed) = match event.event_type {
+                        EventType::KeyPress(key) => (key, true),
+                        EventType::KeyRelease(key) => (key, false),
+                        _ => return,
+                    };
+                    match key {
+                        Key::ControlLeft | Key::ControlRight => ctrl = pressed,
+                        Key::ShiftLeft | Key::ShiftRight => shift = pressed,
+                        _ if pressed && ctrl && shift => {
+                            if let (Some(action), Ok(mut pending)) =
+                                (key_char(key).and_then(|c| hotkeys.action(c)), shared.lock())
+                            {
+                                pending.push(action);
+                            }
+                        }
+                        _ => {}
+                    }
+                });
+                if let Err(err) = listened {
+                    log::error!("global hotkeys unavailable: {:?}", err);
+                }
+            });
+
+            Self { pending }
+        }
+
+        /// Actions pressed since the last call, oldest first.
+        pub fn drain(&self) -> Vec<HotkeyAction> {
+            self.pending
+                .lock()
+                .map(|mut pending| mem::take(&mut *pending))
+                .unwrap_or_default()
+        }
+    }
+
+    /// The letter or digit on `key`. Read from the key instead of the typed text, which is a
+    /// control character or missing while Ctrl is held.
+    fn key_char(key: Key) -> Option<char> {
+        let c = match key {
+            Key::KeyA => 'a',
+            Key::KeyB => 'b',
+            Key::KeyC => 'c',
+            Key::KeyD => 'd',
+            Key::KeyE => 'e',
+            Key::KeyF => 'f',
+            Key::KeyG => 'g',
+            Key::KeyH => 'h',
+            Key::KeyI => 'i',
+            Key::KeyJ => 'j',
+            Key::KeyK => 'k',
+            Key::KeyL => 'l',
+            Key::KeyM => 'm',
+            Key::KeyN => 'n',
+            Key::KeyO => 'o',
+            Key::KeyP => 'p',
+            Key::KeyQ => 'q',
+            Key::KeyR => 'r',
+            Key::KeyS => 's',
+            Key::KeyT => 't',
+            Key::KeyU => 'u',
+            Key::KeyV => 'v',
+            Key::KeyW => 'w',
+            Key::KeyX => 'x',
+            Key::KeyY => 'y',
+            Key::KeyZ => 'z',
+            Key::Num0 => '0',
+            Key::Num1 => '1',
+            Key::Num2 => '2',
+            Key::Num3 => '3',
+            Key::Num4 => '4',
+            Key::Num5 => '5',
+            Key::Num6 => '6',
+            Key::Num7 => '7',
+            Key::Num8 => '8',
+            Key::Num9 => '9',
+            _ => return None,
+        };
+        Some(c)
+    }
+}
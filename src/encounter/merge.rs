@@ -0,0 +1,164 @@
+use super::{EncounterState, RouteStats};
+
+impl EncounterState {
+    /// Adds the counts of a hunt done elsewhere, e.g. on another PC. The history of `other`
+    /// is appended after this one with its encounter and battle numbers, and the first and
+    /// last seen of its species, moved past ours. The last encounter, chain and mode are
+    /// taken from whichever state saw an encounter most recently.
+    pub fn merge(&mut self, other: &EncounterState) {
+        let encounter_offset = self.encounters;
+        let battle_offset = self.battle_count;
+        let reset_offset = self.resets;
+        let moved = |seen: u32| match seen {
+            0 => 0,
+            _ => seen + encounter_offset,
+        };
+
+        for (name, stat) in &other.mon_stats {
+            let name = self.stored_names(vec![name.clone()]).remove(0);
+            let merged = self.mon_stats.entry(name).or_default();
+            // 0 is unknown, from a version that didn't track it.
+            merged.first_seen = match (merged.first_seen, moved(stat.first_seen)) {
+                (0, seen) | (seen, 0) => seen,
+                (ours, theirs) => ours.min(theirs),
+            };
+            merged.last_seen = merged.last_seen.max(moved(stat.last_seen));
+            merged.count += stat.count;
+            merged.longest_chain = merged.longest_chain.max(stat.longest_chain);
+        }
+
+        self.history.extend(other.history.iter().map(|record| {
+            let mut record = record.clone();
+            record.encounter += encounter_offset;
+            record.battle += battle_offset;
+            record.resets += reset_offset;
+            record
+        }));
+
+        for (route, stats) in &other.routes {
+            self.routes.entry(route.clone()).or_default().add(stats);
+        }
+        for (method, stats) in &other.method_stats {
+            self.method_stats
+                .entry(method.clone())
+                .or_default()
+                .add(stats);
+        }
+        // Names held back in one hunt may be species in the other by now.
+        let mut pending = std::mem::take(&mut self.pending_species);
+        for (name, seen) in &other.pending_species {
+            *pending.entry(name.clone()).or_default() += seen;
+        }
+        for (name, seen) in pending {
+            match self.mon_stats.get_mut(&name) {
+                Some(stat) => stat.count += seen,
+                None => {
+                    self.pending_species.insert(name, seen);
+                }
+            }
+        }
+
+        self.encounters += other.encounters;
+        self.battle_count += other.battle_count;
+        self.active_ms += other.active_ms;
+        self.resets += other.resets;
+        self.eggs_hatched += other.eggs_hatched;
+        if other.last_encounter_ms > self.last_encounter_ms {
+            self.last_encounter = other.last_encounter.clone();
+            self.current_chain = other.current_chain.clone();
+            self.last_encounter_ms = other.last_encounter_ms;
+            self.mode = other.mode.clone();
+        }
+    }
+}
+
+impl RouteStats {
+    fn add(&mut self, other: &RouteStats) {
+        self.encounters += other.encounters;
+        self.battle_count += other.battle_count;
+        for (name, count) in &other.mon_counts {
+            *self.mon_counts.entry(name.clone()).or_default() += count;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encounter::MonStat;
+    use std::collections::HashMap;
+
+    fn hunt(encounters: u32, species: &[(&str, u32, u32)]) -> EncounterState {
+        EncounterState {
+            encounters,
+            mon_stats: species
+                .iter()
+                .map(|(name, first_seen, last_seen)| {
+                    let stat = MonStat {
+                        count: 1,
+                        first_seen: *first_seen,
+                        last_seen: *last_seen,
+                        longest_chain: 1,
+                    };
+                    (name.to_string(), stat)
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn first_seen_is_the_earliest_known() {
+        let mut ours = hunt(10, &[("Abra", 0, 0), ("Zubat", 4, 9), ("Oddish", 2, 2)]);
+        let theirs = hunt(5, &[("Abra", 3, 5), ("Zubat", 0, 0), ("Pidgey", 1, 1)]);
+        ours.merge(&theirs);
+
+        let seen = |name: &str| {
+            let stat = &ours.mon_stats[name];
+            (stat.first_seen, stat.last_seen)
+        };
+        assert_eq!(seen("Abra"), (13, 15));
+        assert_eq!(seen("Zubat"), (4, 9));
+        assert_eq!(seen("Oddish"), (2, 2));
+        assert_eq!(seen("Pidgey"), (11, 11));
+    }
+
+    #[test]
+    fn history_is_numbered_after_ours() {
+        let mut ours = EncounterState::default();
+        ours.count_battle(vec!["Abra".to_string(); 2], vec![], 1, 1);
+        ours.resets = 3;
+        let mut theirs = EncounterState::default();
+        theirs.resets = 1;
+        theirs.count_battle(vec!["Zubat".to_string()], vec![], 1, 1);
+        ours.merge(&theirs);
+
+        let moved = &ours.history[1];
+        assert_eq!((moved.encounter, moved.battle, moved.resets), (3, 2, 4));
+        assert_eq!((ours.encounters, ours.battle_count, ours.resets), (3, 2, 4));
+        assert_eq!(ours.mon_stats["Zubat"].first_seen, 3);
+    }
+
+    #[test]
+    fn species_in_another_casing_are_merged() {
+        let mut ours = hunt(1, &[("Mr. Mime", 1, 1)]);
+        ours.merge(&hunt(1, &[("mr. mime", 1, 1)]));
+        assert_eq!(ours.mon_stats.len(), 1);
+        assert_eq!(ours.mon_stats["Mr. Mime"].count, 2);
+    }
+
+    #[test]
+    fn held_back_names_count_once_either_hunt_has_the_species() {
+        let mut ours = hunt(5, &[("Abra", 1, 1)]);
+        ours.pending_species = [("Ditto".to_string(), 2)].into();
+        let mut theirs = hunt(5, &[("Ditto", 1, 1)]);
+        theirs.pending_species = [("Ditto".to_string(), 1), ("Mew".to_string(), 1)].into();
+        ours.merge(&theirs);
+
+        assert_eq!(ours.mon_stats["Ditto"].count, 1 + 2 + 1);
+        assert_eq!(
+            ours.pending_species,
+            HashMap::from([("Mew".to_string(), 1)])
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use serde_json::{json, Value};
+
+use super::filter::display_name;
+use super::{EncounterError, EncounterState};
+
+/// Version 3 shows mon names as `display_name`, earlier versions stored them lowercased.
+#[cfg(feature = "sqlite")]
+pub(super) const DISPLAY_NAMES_VERSION: u32 = 3;
+
+/// Layout version written with every saved state. Bump it together with a new step in `migrate`.
+pub const STATE_VERSION: u32 = 3;
+
+/// Layout version of a saved state, 0 for files from before versioning.
+pub(super) fn stored_version(value: &Value) -> u32 {
+    value.get("version").and_then(Value::as_u64).unwrap_or(0) as u32
+}
+
+/// Upgrades a state file of any older layout to the current one.
+/// Fields missing in old layouts are filled with their defaults.
+pub(super) fn migrate(mut value: Value) -> Result<EncounterState, EncounterError> {
+    let mut version = stored_version(&value);
+    if version > STATE_VERSION {
+        return Err(EncounterError::UnsupportedVersion(version));
+    }
+
+    while version < STATE_VERSION {
+        match version {
+            0 => from_v0(&mut value),
+            1 => from_v1(&mut value),
+            2 => from_v2(&mut value),
+            _ => unreachable!("no migration from state version {}", version),
+        }
+        version += 1;
+    }
+
+    let mut state: EncounterState = serde_json::from_value(value)?;
+    state.version = STATE_VERSION;
+    Ok(state)
+}
+
+/// Files without a version don't count battles yet, every logged encounter was one battle.
+fn from_v0(value: &mut Value) {
+    let Some(state) = value.as_object_mut() else {
+        return;
+    };
+    if !state.contains_key("battle_count") {
+        let battles = state
+            .get("history")
+            .and_then(Value::as_array)
+            .map_or(0, |history| history.len());
+        state.insert("battle_count".to_string(), battles.into());
+    }
+}
+
+/// Version 1 only counts per species, when it was first and last seen is unknown.
+fn from_v1(value: &mut Value) {
+    let Some(stats) = value.get_mut("mon_stats").and_then(Value::as_object_mut) else {
+        return;
+    };
+    for stat in stats.values_mut() {
+        if let Some(count) = stat.as_u64() {
+            *stat = json!({ "count": count, "first_seen": 0, "last_seen": 0 });
+        }
+    }
+}
+
+fn display_names(names: Option<&mut Value>) {
+    for name in names.and_then(Value::as_array_mut).into_iter().flatten() {
+        if let Some(display) = name.as_str().map(display_name) {
+            *name = display.into();
+        }
+    }
+}
+
+fn display_keys(map: Option<&mut Value>) {
+    if let Some(map) = map.and_then(Value::as_object_mut) {
+        *map = std::mem::take(map)
+            .into_iter()
+            .map(|(name, value)| (display_name(&name), value))
+            .collect();
+    }
+}
+
+/// Version 2 stores every mon name lowercased, they are shown as `display_name` since.
+fn from_v2(value: &mut Value) {
+    display_keys(value.get_mut("mon_stats"));
+    display_names(value.get_mut("last_encounter"));
+    if let Some(chain) = value.pointer_mut("/current_chain/0") {
+        if let Some(display) = chain.as_str().map(display_name) {
+            *chain = display.into();
+        }
+    }
+    for record in value
+        .get_mut("history")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+    {
+        display_names(record.get_mut("mons"));
+        display_names(record.get_mut("new_species"));
+    }
+    for route in value
+        .get_mut("routes")
+        .and_then(Value::as_object_mut)
+        .into_iter()
+        .flat_map(|routes| routes.values_mut())
+    {
+        display_keys(route.get_mut("mon_counts"));
+    }
+}
+
+/// `from_v2` for the names a SQLite store keeps in its tables instead of the JSON.
+#[cfg(feature = "sqlite")]
+pub(super) fn display_table_names(state: &mut EncounterState) {
+    state.mon_stats = std::mem::take(&mut state.mon_stats)
+        .into_iter()
+        .map(|(name, stat)| (display_name(&name), stat))
+        .collect();
+    for record in &mut state.history {
+        for name in record.mons.iter_mut().chain(&mut record.new_species) {
+            *name = display_name(name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encounter::MonStat;
+
+    /// A state file with `fields` over the keys every version has.
+    fn stored(fields: Value) -> Value {
+        let mut value = json!({
+            "encounters": 2,
+            "last_encounter": [],
+            "mode": "Init",
+            "mon_stats": {},
+            "lure_on": false,
+            "toggle": "Runaway",
+            "debug": false,
+        });
+        let extra = fields.as_object().cloned().unwrap_or_default();
+        value.as_object_mut().unwrap().extend(extra);
+        value
+    }
+
+    fn record(mons: &[&str], encounter: u32) -> Value {
+        json!({ "mons": mons, "new_species": mons, "timestamp": 0, "encounter": encounter })
+    }
+
+    #[test]
+    fn version_0_counts_every_logged_encounter_as_a_battle() {
+        let state = migrate(stored(json!({
+            "mon_stats": { "pidgey": 2 },
+            "history": [record(&["pidgey"], 1), record(&["pidgey"], 2)],
+        })))
+        .unwrap();
+
+        assert_eq!(state.version, STATE_VERSION);
+        assert_eq!(state.battle_count, 2);
+        assert_eq!(state.mon_stats["Pidgey"].count, 2);
+        assert_eq!(state.history[1].mons, ["Pidgey"]);
+    }
+
+    #[test]
+    fn version_0_keeps_a_battle_count_it_has() {
+        let state = migrate(stored(json!({
+            "battle_count": 1,
+            "history": [record(&["oddish", "oddish"], 2)],
+        })))
+        .unwrap();
+        assert_eq!(state.battle_count, 1);
+    }
+
+    #[test]
+    fn version_1_counts_become_stats() {
+        let state = migrate(stored(json!({ "version": 1, "mon_stats": { "abra": 3 } }))).unwrap();
+
+        let abra = MonStat {
+            count: 3,
+            ..MonStat::default()
+        };
+        assert_eq!(state.mon_stats["Abra"], abra);
+    }
+
+    #[test]
+    fn version_2_names_are_shown_as_display_names() {
+        let state = migrate(stored(json!({
+            "version": 2,
+            "mon_stats": { "mr. mime": { "count": 1, "first_seen": 1, "last_seen": 1 } },
+            "last_encounter": ["mr. mime"],
+            "current_chain": ["mr. mime", 1],
+            "history": [record(&["mr. mime"], 1)],
+            "routes": {
+                "route 1": { "encounters": 1, "battle_count": 1, "mon_counts": { "mr. mime": 1 } },
+            },
+        })))
+        .unwrap();
+
+        assert!(state.mon_stats.contains_key("Mr. Mime"));
+        assert_eq!(state.last_encounter, ["Mr. Mime"]);
+        assert_eq!(state.current_chain, ("Mr. Mime".to_string(), 1));
+        assert_eq!(state.history[0].mons, ["Mr. Mime"]);
+        assert_eq!(state.history[0].new_species, ["Mr. Mime"]);
+        assert_eq!(state.routes["route 1"].mon_counts["Mr. Mime"], 1);
+    }
+
+    #[test]
+    fn current_names_are_left_alone() {
+        let state = migrate(stored(json!({
+            "version": STATE_VERSION,
+            "last_encounter": ["mr. mime"],
+        })))
+        .unwrap();
+        assert_eq!(state.last_encounter, ["mr. mime"]);
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let newer = stored(json!({ "version": STATE_VERSION + 1 }));
+        assert!(matches!(
+            migrate(newer),
+            Err(EncounterError::UnsupportedVersion(version)) if version == STATE_VERSION + 1
+        ));
+    }
+}
@@ -1,17 +1,94 @@
 use core::panic;
+use image::imageops::FilterType;
 use image::{DynamicImage, RgbImage};
-use ocrs::{ImageSource, OcrEngine};
+use log::{debug, info, warn};
+use ocrs::{ImageSource, OcrEngine, OcrInput};
+use rten_imageproc::{Point, RotatedRect};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::error::Error;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use xcap::Window;
 
+#[cfg(feature = "tokio")]
+mod async_process;
+mod background;
+mod calibrate;
+mod config;
+mod dictionary;
+mod error;
+mod export;
+mod filter;
+mod hotkeys;
+mod merge;
+mod migrate;
+mod overlay;
+mod recognizer;
+mod recompute;
+mod shutdown;
+mod source;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod stats;
+mod store;
+mod stream;
+mod summary;
+#[cfg(feature = "tui")]
+mod targets;
+mod threshold;
+mod timings;
+mod tracker;
+#[cfg(feature = "video")]
+mod video;
+mod vote;
+
+#[cfg(feature = "tokio")]
+pub use async_process::encounter_process_async;
+pub use background::{BackgroundSource, TimedSource};
+pub use calibrate::calibrate;
+pub use config::{
+    load_config, CaptureConfig, CaptureRegion, Config, DetectionConfig, DetectionMode,
+    LanguageConfig, NamePattern, NormalizeRule, PixelOrder, PreprocessConfig, ThresholdMode,
+};
+pub use dictionary::{SpeciesDictionary, DEFAULT_MAX_DISTANCE};
+pub use error::EncounterError;
+pub use export::print_state;
+pub use filter::{
+    mode_filter, DefaultMonFilter, DetectedWord, DiscardReason, HatchFilter, MonFilter,
+    ReviewedFilter, TextFilter,
+};
+#[cfg(feature = "global-hotkeys")]
+pub use hotkeys::GlobalHotkeys;
+#[cfg(feature = "tui")]
+pub use hotkeys::HotkeyAction;
+pub use hotkeys::Hotkeys;
+use migrate::STATE_VERSION;
+pub use overlay::{debug_region_overlay, trace_ocr, OcrTrace, TextBox};
+pub use recognizer::{FrameReading, MonRecognizer, SkipStaticFrames, TimedOcr};
+pub use shutdown::stop_on_signal;
+pub use source::{
+    game_windows, list_displays, live_source, screen_source, DisplaySource, FileSource,
+    GameWindowSource, ScreenSource, WindowSource,
+};
+pub use stats::DEFAULT_SHINY_ODDS;
+#[cfg(feature = "tui")]
+pub use stats::{OddsReport, SessionStats};
+pub use store::{open_store, FileStore, StateStore, ThrottledStore};
+pub use stream::{encounter_stream, EncounterStream};
+pub use summary::{hours_minutes, Summary};
+pub use timings::StageTimings;
+pub use tracker::Tracker;
+#[cfg(feature = "video")]
+pub use video::VideoSource;
+
 pub const APP_NAME: &str = "pokemmo";
 pub const JAVA: &str = "java";
-const ENCOUNTER_DETECT_FRAMES: i32 = 2;
+pub const DEFAULT_STATE_FILE: &str = "state.json";
+const IMAGE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "bmp"];
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Mode {
@@ -61,31 +138,373 @@ impl std::fmt::Display for Toggle {
     }
 }
 
+/// Encounters of a single species. `first_seen` and `last_seen` are encounter numbers,
+/// 0 for species counted before they were tracked.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MonStat {
+    pub count: u32,
+    pub first_seen: u32,
+    pub last_seen: u32,
+    /// Most battles in a row against only this species.
+    #[serde(default)]
+    pub longest_chain: u32,
+}
+
+/// Counts of one route or encounter method, see `EncounterState::set_route` and
+/// `EncounterState::set_method`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteStats {
+    pub encounters: u32,
+    pub battle_count: u32,
+    /// Encounters per species on this route.
+    pub mon_counts: HashMap<String, u32>,
+}
+
+impl RouteStats {
+    /// Counts a battle of `encounters` encounters, adding `counts` to the species in it. Takes
+    /// the same numbers as the totals of `EncounterState`, so the two never drift apart.
+    fn count_battle(&mut self, encounters: u32, counts: &[(String, u32)]) {
+        self.encounters += encounters;
+        self.battle_count += 1;
+        for (mon, count) in counts {
+            *self.mon_counts.entry(mon.clone()).or_default() += count;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncounterRecord {
+    pub mons: Vec<String>,
+    /// Seconds since the Unix epoch (UTC).
+    pub timestamp: u64,
+    /// Value of `encounters` once this encounter was counted.
+    pub encounter: u32,
+    /// Value of `battle_count` once this encounter was counted. `mons` holds the whole horde.
+    #[serde(default)]
+    pub battle: u32,
+    /// Mons of this encounter never counted before in the state file.
+    #[serde(default)]
+    pub new_species: Vec<String>,
+    /// Frames the mons were read on, updated in `history` when the battle ends.
+    /// 0 for encounters added by hand.
+    #[serde(default)]
+    pub frames: u32,
+    /// The mons were gone after fewer than `brief_encounter_frames` frames, e.g. they fled.
+    #[serde(default)]
+    pub brief: bool,
+    /// Encounter method the battle was counted under, see `EncounterState::set_method`.
+    #[serde(default)]
+    pub method: Option<String>,
+    /// Value of `resets` once this encounter was counted.
+    #[serde(default)]
+    pub resets: u32,
+    /// OCR text of every mon in `mons`, in the same order, for `EncounterState::recompute_stats`.
+    /// Empty for encounters from before it was kept, their names are taken as read then.
+    #[serde(default)]
+    pub raw: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EncounterState {
+    /// Layout of the state file, missing in files from before versioning.
+    #[serde(default)]
+    pub version: u32,
+    /// Mons encountered, a horde of five counts as five.
     pub encounters: u32,
+    /// Battles started, a horde counts as one.
+    #[serde(default)]
+    pub battle_count: u32,
     pub last_encounter: Vec<String>,
     pub mode: Mode,
-    pub mon_stats: HashMap<String, u32>,
+    pub mon_stats: HashMap<String, MonStat>,
     pub lure_on: bool,
     pub toggle: Toggle,
     pub debug: bool,
+    /// Append-only log of every counted encounter.
+    #[serde(default)]
+    pub history: Vec<EncounterRecord>,
+    /// Species of the running chain and the battles in a row against only that species.
+    #[serde(default)]
+    pub current_chain: (String, u32),
+    /// Mon the shiny hunt is about.
+    #[serde(default)]
+    pub target_mon: Option<String>,
+    /// One in `shiny_odds` encounters is shiny.
+    #[serde(default = "default_shiny_odds")]
+    pub shiny_odds: u32,
+    /// Milliseconds since the Unix epoch at which the last encounter was detected, 0 before the first.
+    #[serde(default)]
+    pub last_encounter_ms: u64,
+    /// Route encounters are counted on, none when empty. `encounters` and `mon_stats`
+    /// stay the totals over every route.
+    #[serde(default)]
+    pub current_route: String,
+    #[serde(default)]
+    pub routes: HashMap<String, RouteStats>,
+    /// Method encounters are counted under, e.g. grass, surf or fishing. Like routes,
+    /// the totals stay over every method.
+    #[serde(default)]
+    pub current_method: Option<String>,
+    #[serde(default)]
+    pub method_stats: HashMap<String, RouteStats>,
+    /// Detection cycles in a row that read no mons during the running battle.
+    #[serde(default)]
+    pub empty_cycles: u32,
+    /// Names never counted before with the encounters they were read in, held back until
+    /// `new_species_encounters` is reached.
+    #[serde(default)]
+    pub pending_species: HashMap<String, u32>,
+    /// Milliseconds spent hunting over every session, time in Init or Pause is not counted.
+    #[serde(default)]
+    pub active_ms: u64,
+    /// When `active_ms` was last brought up to date, `None` while not hunting.
+    #[serde(skip)]
+    pub(crate) active_since: Option<Instant>,
+    /// Soft resets done for a stationary hunt, detected by `reset_tokens` or added by hand.
+    #[serde(default)]
+    pub resets: u32,
+    /// Whether the last cycle showed a reset cue, so a title screen shown for a while
+    /// counts once.
+    #[serde(default)]
+    pub on_reset_screen: bool,
+    /// Eggs hatched in `DetectionMode::Hatch`, each also counted as an encounter.
+    #[serde(default)]
+    pub eggs_hatched: u32,
+}
+
+fn default_shiny_odds() -> u32 {
+    DEFAULT_SHINY_ODDS
+}
+
+impl EncounterState {
+    /// Counts one encounter that detection missed, for `mon` when it is known.
+    /// The caller saves the state.
+    #[cfg(feature = "tui")]
+    pub fn record_manual(&mut self, mon: Option<String>) -> EncounterRecord {
+        let mons = self.stored_names(mon.into_iter().collect());
+        let raw = mons.clone();
+        self.count_battle(mons, raw, 0, 1)
+    }
+
+    /// Counts a soft reset that detection missed or that has no cue configured.
+    /// The caller saves the state.
+    #[cfg(feature = "tui")]
+    pub fn record_reset(&mut self) {
+        self.resets += 1;
+    }
+
+    /// The last `n` encounters in `history`, oldest first.
+    #[cfg(any(feature = "tui", feature = "http"))]
+    pub fn recent_encounters(&self, n: usize) -> &[EncounterRecord] {
+        &self.history[self.history.len().saturating_sub(n)..]
+    }
+
+    /// Stats of `name`, ignoring case.
+    pub fn mon_stat(&self, name: &str) -> Option<&MonStat> {
+        let lowercase = name.to_lowercase();
+        self.mon_stats
+            .iter()
+            .find(|(species, _)| species.to_lowercase() == lowercase)
+            .map(|(_, stat)| stat)
+    }
+
+    /// Replaces every name that is already counted in another casing by the counted one,
+    /// so a species is never split over two keys.
+    fn stored_names(&self, mons: Vec<String>) -> Vec<String> {
+        mons.into_iter()
+            .map(|mon| {
+                let lowercase = mon.to_lowercase();
+                self.mon_stats
+                    .keys()
+                    .find(|species| species.to_lowercase() == lowercase)
+                    .cloned()
+                    .unwrap_or(mon)
+            })
+            .collect()
+    }
+
+    /// Counts the following encounters on `name` as well, an empty name stops tracking routes.
+    pub fn set_route(&mut self, name: &str) {
+        self.current_route = name.trim().to_lowercase();
+    }
+
+    /// Counts of the current route, `None` when no route is set or nothing was counted on it yet.
+    #[cfg(any(feature = "tui", test))]
+    pub fn route_stats(&self) -> Option<&RouteStats> {
+        self.routes.get(&self.current_route)
+    }
+
+    /// Tags the following encounters with method `m`, an empty method stops tagging them.
+    pub fn set_method(&mut self, m: &str) {
+        let method = m.trim().to_lowercase();
+        self.current_method = (!method.is_empty()).then_some(method);
+    }
+
+    /// Counts of the current method, `None` when no method is set or nothing was counted under it.
+    #[cfg(any(feature = "tui", test))]
+    pub fn current_method_stats(&self) -> Option<&RouteStats> {
+        self.method_stats.get(self.current_method.as_ref()?)
+    }
+
+    /// Extends the chain when every mon of the battle is its species, otherwise a new chain starts.
+    /// A battle against mixed species ends the chain.
+    fn update_chain(&mut self, mons: &[String]) {
+        let species = match mons.split_first() {
+            Some((first, rest)) if rest.iter().all(|mon| mon == first) => first,
+            _ => {
+                self.current_chain = (String::new(), 0);
+                return;
+            }
+        };
+
+        if self.current_chain.0 == *species {
+            self.current_chain.1 += 1;
+        } else {
+            self.current_chain = (species.clone(), 1);
+        }
+
+        // Names still held back in `pending_species` have no stats to keep a chain in.
+        if let Some(stat) = self.mon_stats.get_mut(species) {
+            stat.longest_chain = stat.longest_chain.max(self.current_chain.1);
+        }
+    }
+
+    /// Names of `mons` never counted before that, including this battle, were read in fewer
+    /// than `needed` encounters. Their encounters are kept in `pending_species`.
+    fn hold_new_species(&mut self, mons: &[String], needed: u32) -> Vec<String> {
+        let mut held: Vec<String> = vec![];
+        for mon in mons {
+            if self.mon_stats.contains_key(mon) || held.contains(mon) {
+                continue;
+            }
+            let seen = self.pending_species.entry(mon.clone()).or_default();
+            if *seen + 1 < needed {
+                *seen += 1;
+                held.push(mon.clone());
+            }
+        }
+        held
+    }
+
+    /// Counts a battle against `mons`, read from the OCR text `raw` on `frames` frames so far,
+    /// and logs it to `history`. A battle without known mons still counts as one encounter.
+    /// Names never counted before become a species once read in `new_species_encounters`
+    /// encounters, together with the encounters they were held back in.
+    fn count_battle(
+        &mut self,
+        mons: Vec<String>,
+        raw: Vec<String>,
+        frames: u32,
+        new_species_encounters: u32,
+    ) -> EncounterRecord {
+        let held = self.hold_new_species(&mons, new_species_encounters);
+        let counted = mons
+            .iter()
+            .filter(|mon| !held.contains(mon))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut new_species: Vec<String> = vec![];
+        for mon in &counted {
+            if !self.mon_stats.contains_key(mon) && !new_species.contains(mon) {
+                new_species.push(mon.clone());
+            }
+        }
+
+        // What every counted mon adds, one more than it was held back for when it is promoted.
+        let mut counts: Vec<(String, u32)> = vec![];
+        for (mon, encounter) in mons.iter().zip(self.encounters + 1..) {
+            if held.contains(mon) {
+                continue;
+            }
+            let count = 1 + self.pending_species.remove(mon).unwrap_or(0);
+            let stat = self.mon_stats.entry(mon.clone()).or_default();
+            if stat.first_seen == 0 {
+                stat.first_seen = encounter;
+            }
+            stat.last_seen = encounter;
+            stat.count += count;
+            counts.push((mon.clone(), count));
+        }
+
+        let encounters = mons.len().max(1) as u32;
+        self.encounters += encounters;
+        self.battle_count += 1;
+        if !self.current_route.is_empty() {
+            self.routes
+                .entry(self.current_route.clone())
+                .or_default()
+                .count_battle(encounters, &counts);
+        }
+        if let Some(method) = &self.current_method {
+            self.method_stats
+                .entry(method.clone())
+                .or_default()
+                .count_battle(encounters, &counts);
+        }
+        if !mons.is_empty() {
+            self.last_encounter = mons.clone();
+        }
+
+        let record = EncounterRecord {
+            mons,
+            timestamp: unix_timestamp(),
+            encounter: self.encounters,
+            battle: self.battle_count,
+            new_species,
+            frames,
+            brief: false,
+            method: self.current_method.clone(),
+            resets: self.resets,
+            raw,
+        };
+        self.history.push(record.clone());
+        record
+    }
 }
 
 impl Default for EncounterState {
     fn default() -> Self {
         Self {
+            version: STATE_VERSION,
             encounters: 0,
+            battle_count: 0,
             last_encounter: vec![],
             mode: Mode::Init,
             mon_stats: HashMap::new(),
             lure_on: false,
             toggle: Toggle::Runaway,
             debug: false,
+            history: vec![],
+            current_chain: (String::new(), 0),
+            target_mon: None,
+            shiny_odds: DEFAULT_SHINY_ODDS,
+            last_encounter_ms: 0,
+            current_route: String::new(),
+            routes: HashMap::new(),
+            current_method: None,
+            method_stats: HashMap::new(),
+            empty_cycles: 0,
+            pending_species: HashMap::new(),
+            active_ms: 0,
+            active_since: None,
+            resets: 0,
+            on_reset_screen: false,
+            eggs_hatched: 0,
         }
     }
 }
 
+fn unix_timestamp() -> u64 {
+    unix_millis() / 1000
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
 pub fn game_exist(w: &&Window) -> bool {
     let name = convert_cyrillic_string(w.app_name().to_lowercase().as_str());
     let title = convert_cyrillic_string(w.title().to_lowercase().as_str());
@@ -115,116 +534,1249 @@ pub fn get_current_working_dir() -> (String, String) {
     }
 }
 
-pub fn load_state() -> Result<EncounterState, Box<dyn Error>> {
-    let state_json = fs::read_to_string("state.json")?;
-    let state = serde_json::from_str(&state_json)?;
-    Ok(state)
+/// Loads the state saved at `path`, see `open_store`.
+pub fn load_state(path: &Path) -> Result<EncounterState, EncounterError> {
+    open_store(path).load()
 }
 
-pub fn save_state(state: &EncounterState) -> Result<(), Box<dyn Error>> {
-    let state_json = serde_json::to_string(state)?;
-    fs::write("state.json", state_json)?;
-    Ok(())
+/// Loads the state saved at `path`, a fresh one when there is none yet. See
+/// `StateStore::load_or_default`.
+pub fn load_state_or_default(path: &Path) -> Result<EncounterState, EncounterError> {
+    open_store(path).load_or_default()
 }
 
-fn get_mons(engine: &OcrEngine, data: RgbImage) -> Result<(Vec<String>, bool), Box<dyn Error>> {
-    let img = ImageSource::from_bytes(data.as_raw(), data.dimensions())?;
-    let ocr_input = engine.prepare_input(img)?;
-    let word_rects = engine.detect_words(&ocr_input)?;
-    let line_rects = engine.find_text_lines(&ocr_input, &word_rects);
-    let line_texts = engine.recognize_text(&ocr_input, &line_rects)?;
+/// Saves the state to `path`, see `open_store`.
+pub fn save_state(state: &EncounterState, path: &Path) -> Result<(), EncounterError> {
+    open_store(path).save(state)
+}
 
-    let mut mons = Vec::new();
-    let mut lure_on = false;
+/// Saves the state to `path` like `save_state`, also writing over encounters that are already
+/// stored, e.g. after `recompute_stats` changed them.
+pub fn replace_state(state: &EncounterState, path: &Path) -> Result<(), EncounterError> {
+    #[cfg(feature = "sqlite")]
+    if sqlite::is_database(path) {
+        return sqlite::replace_state(state, path);
+    }
 
-    for line in line_texts
+    save_state(state, path)
+}
+
+/// Moves the state file into `archive_dir` as `<name>-<unix timestamp>.<extension>` and returns a fresh state.
+#[cfg(feature = "tui")]
+pub fn reset_state(
+    state_path: &Path,
+    archive_dir: &Path,
+) -> Result<EncounterState, EncounterError> {
+    if state_path.exists() {
+        fs::create_dir_all(archive_dir)?;
+        let name = state_path
+            .file_stem()
+            .map_or("state".into(), |stem| stem.to_string_lossy());
+        let extension = state_path
+            .extension()
+            .map_or("json".into(), |ext| ext.to_string_lossy());
+        let archive_path = archive_dir.join(format!("{}-{}.{}", name, unix_timestamp(), extension));
+        fs::rename(state_path, archive_path)?;
+    }
+
+    Ok(EncounterState::default())
+}
+
+/// Hands `data` to the engine as interleaved 8-bit rows, see `frame_bytes`. The engine builds
+/// and normalizes the `[channels, height, width]` tensor itself, so no pixel math happens here.
+fn prepare_frame(engine: &OcrEngine, data: &DynamicImage) -> Result<OcrInput, EncounterError> {
+    let bytes = frame_bytes(data);
+    let img = ImageSource::from_bytes(&bytes, (data.width(), data.height()))
+        .map_err(EncounterError::ocr)?;
+    engine.prepare_input(img).map_err(EncounterError::ocr)
+}
+
+/// The pixel buffer the engine reads for `data`. Gray, RGB and RGBA frames are read in place,
+/// only other layouts are converted to RGB first.
+fn frame_bytes(data: &DynamicImage) -> Cow<'_, [u8]> {
+    match data {
+        DynamicImage::ImageLuma8(_) | DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) => {
+            Cow::Borrowed(data.as_bytes())
+        }
+        _ => Cow::Owned(data.to_rgb8().into_raw()),
+    }
+}
+
+/// Grows every word of `lines` by `padding` pixels on each side, kept inside a `width` x `height`
+/// image, so recognition also sees the tops and bottoms that tight word boxes cut off.
+/// Words grow along their own axes, a skewed line keeps its angle.
+fn pad_lines(
+    lines: Vec<Vec<RotatedRect>>,
+    padding: u32,
+    width: u32,
+    height: u32,
+) -> Vec<Vec<RotatedRect>> {
+    if padding == 0 {
+        return lines;
+    }
+
+    let padding = padding as f32;
+    let size = (width as f32, height as f32);
+    lines
+        .into_iter()
+        .map(|words| {
+            words
+                .iter()
+                .map(|word| pad_word(word, padding, size))
+                .collect()
+        })
+        .collect()
+}
+
+/// `word` grown by up to `padding` on each side. A side at the edge of the `size` image grows
+/// less, and when a corner of a skewed word would still leave it, every side grows less by the
+/// same share.
+fn pad_word(word: &RotatedRect, padding: f32, size: (f32, f32)) -> RotatedRect {
+    let center = word.center();
+    let up = (word.up_axis().x, word.up_axis().y);
+    let across = (-up.1, up.0);
+    // A move of `along` across the word and `high` up it, in image coordinates.
+    let offset = |along: f32, high: f32| {
+        (
+            along * across.0 + high * up.0,
+            along * across.1 + high * up.1,
+        )
+    };
+    let corner = |along: f32, high: f32| {
+        let (x, y) = offset(along * word.width() / 2., high * word.height() / 2.);
+        (center.x + x, center.y + y)
+    };
+    let room = |points: &[(f32, f32)], step: (f32, f32), max: f32| {
+        points
+            .iter()
+            .fold(max, |room, &(x, y)| {
+                room.min(axis_room(x, step.0, size.0))
+                    .min(axis_room(y, step.1, size.1))
+            })
+            .max(0.)
+    };
+
+    let right = room(&[corner(1., 1.), corner(1., -1.)], offset(1., 0.), padding);
+    let left = room(
+        &[corner(-1., 1.), corner(-1., -1.)],
+        offset(-1., 0.),
+        padding,
+    );
+    let top = room(&[corner(1., 1.), corner(-1., 1.)], offset(0., 1.), padding);
+    let bottom = room(
+        &[corner(1., -1.), corner(-1., -1.)],
+        offset(0., -1.),
+        padding,
+    );
+    // Every side fits on its own, but a corner that two sides of a skewed word move may not.
+    let share = [
+        (1., 1., right, top),
+        (1., -1., right, bottom),
+        (-1., 1., left, top),
+        (-1., -1., left, bottom),
+    ]
+    .into_iter()
+    .fold(1f32, |share, (along, high, side, end)| {
+        room(
+            &[corner(along, high)],
+            offset(along * side, high * end),
+            share,
+        )
+    });
+    let (right, left, top, bottom) = (right * share, left * share, top * share, bottom * share);
+
+    let (x, y) = offset((right - left) / 2., (top - bottom) / 2.);
+    RotatedRect::new(
+        Point::from_yx(center.y + y, center.x + x),
+        word.up_axis(),
+        word.width() + left + right,
+        word.height() + top + bottom,
+    )
+}
+
+/// How many `step`s a coordinate `at` can move and stay within `0..=size`.
+fn axis_room(at: f32, step: f32, size: f32) -> f32 {
+    if step > 0. {
+        (size - at) / step
+    } else if step < 0. {
+        at / -step
+    } else {
+        f32::INFINITY
+    }
+}
+
+/// Runs OCR over `data` and returns the recognized lines, lowercased. The time of every
+/// OCR stage is added to `timings`. Lines are grown by `line_padding` first, see `pad_lines`.
+fn read_lines(
+    engine: &OcrEngine,
+    data: &DynamicImage,
+    line_padding: u32,
+    timings: &mut StageTimings,
+) -> Result<Vec<String>, EncounterError> {
+    let started = Instant::now();
+    let ocr_input = prepare_frame(engine, data)?;
+    timings.prepare += started.elapsed();
+
+    let started = Instant::now();
+    let word_rects = engine
+        .detect_words(&ocr_input)
+        .map_err(EncounterError::ocr)?;
+    let line_rects = pad_lines(
+        engine.find_text_lines(&ocr_input, &word_rects),
+        line_padding,
+        data.width(),
+        data.height(),
+    );
+    timings.detect += started.elapsed();
+
+    let started = Instant::now();
+    let line_texts = engine
+        .recognize_text(&ocr_input, &line_rects)
+        .map_err(EncounterError::ocr)?;
+    timings.recognize += started.elapsed();
+
+    Ok(line_texts
         .iter()
         .flatten()
         .map(|l| l.to_string().to_lowercase())
-    {
+        .collect())
+}
+
+/// The lines of every region, in order, and the time OCR took on them.
+fn read_regions(
+    engine: &OcrEngine,
+    regions: &[DynamicImage],
+    line_padding: u32,
+) -> Result<(Vec<String>, StageTimings), EncounterError> {
+    let mut lines = vec![];
+    let mut timings = StageTimings::default();
+    for region in regions {
+        lines.extend(read_lines(engine, region, line_padding, &mut timings)?);
+    }
+    Ok((lines, timings))
+}
+
+fn get_mons(
+    engine: &OcrEngine,
+    regions: Vec<DynamicImage>,
+    line_padding: u32,
+    filter: &dyn MonFilter,
+) -> Result<FrameReading, EncounterError> {
+    let (lines, timings) = read_regions(engine, &regions, line_padding)?;
+    Ok(FrameReading {
+        timings,
+        ..reading_from_lines(lines, filter)
+    })
+}
+
+fn reading_from_lines(lines: Vec<String>, filter: &dyn MonFilter) -> FrameReading {
+    let mut reading = FrameReading::default();
+
+    for line in lines {
         if line.contains("lure") {
-            lure_on = true;
+            reading.lure_on = true;
+        }
+        if filter.confirms_battle(&line) {
+            reading.in_battle = true;
+        }
+        if filter.shows_reset(&line) {
+            reading.reset_screen = true;
         }
 
-        if line.contains("lv.") || line.contains("nv.") || line.contains("niv.") {
-            line.split_whitespace()
-                .collect::<Vec<_>>()
-                .windows(2)
-                .filter(|w| (w[1] == "lv." || w[1] == "nv." || w[1] == "niv.") && w[0].len() > 1)
-                .for_each(|w| mons.push(w[0].to_string()));
+        for word in filter.classify(&line) {
+            match word.discarded {
+                None => {
+                    reading.raw_text.insert(word.name.clone(), word.text);
+                    reading.mons.push(word.name);
+                }
+                Some(DiscardReason::NoLevelMarker | DiscardReason::NotBeforeLevelMarker) => {}
+                Some(reason) => debug!("name {:?} filtered: {}", word.text, reason),
+            }
         }
     }
 
-    Ok((mons, lure_on))
+    reading
 }
 
-fn capture_screen(debug: bool, window: &Window) -> Result<RgbImage, Box<dyn Error>> {
-    let get_image = |w: &Window| {
-        let factor = 0.5;
+/// One capture region as seen by `preview_detection`.
+pub struct RegionPreview {
+    /// The region after preprocessing, as handed to OCR but converted to RGB for drawing.
+    pub image: RgbImage,
+    pub trace: OcrTrace,
+    /// Every recognized word and whether it would be counted.
+    pub words: Vec<DetectedWord>,
+}
 
-        let img = w.capture_image()?;
-        let img = DynamicImage::ImageRgba8(img)
-            .crop(0, 0, w.width(), (w.height() as f32 * factor) as u32)
-            .grayscale()
-            .to_rgb8();
+/// Runs a single capture and OCR pass over every region, without touching any state.
+pub fn preview_detection(
+    engine: &OcrEngine,
+    source: &mut dyn ScreenSource,
+    config: &DetectionConfig,
+    filter: &dyn MonFilter,
+) -> Result<Vec<RegionPreview>, EncounterError> {
+    let regions = capture_with_retry(false, source, &config.capture, &config.preprocess)?;
 
-        if debug {
-            img.save("debug.png")?;
+    let mut previews = vec![];
+    for image in regions {
+        let trace = trace_ocr(engine, &image, config.line_padding)?;
+        let words = trace
+            .lines
+            .iter()
+            .flat_map(|(line, _)| filter.classify(&line.to_lowercase()))
+            .collect();
+        previews.push(RegionPreview {
+            image: image.to_rgb8(),
+            trace,
+            words,
+        });
+    }
+    Ok(previews)
+}
+
+/// Rearranges the bytes of every pixel of an RGBA frame from `order` into RGBA.
+/// Frames in other layouts are returned as they are.
+fn reorder_pixels(img: DynamicImage, order: PixelOrder) -> DynamicImage {
+    let DynamicImage::ImageRgba8(mut rgba) = img else {
+        return img;
+    };
+    if order != PixelOrder::Rgba {
+        let [r, g, b, a] = order.rgba_indices();
+        for pixel in rgba.pixels_mut() {
+            let bytes = pixel.0;
+            pixel.0 = [bytes[r], bytes[g], bytes[b], bytes[a]];
         }
+    }
+    DynamicImage::ImageRgba8(rgba)
+}
+
+/// Gray frames stay single channel and color frames keep the layout of the capture,
+/// so OCR reads them without an extra RGB copy.
+fn preprocess(mut img: DynamicImage, config: &PreprocessConfig) -> DynamicImage {
+    if config.scale > 0.0 && config.scale != 1.0 {
+        let width = ((img.width() as f32 * config.scale) as u32).max(1);
+        let height = ((img.height() as f32 * config.scale) as u32).max(1);
+        img = img.resize_exact(width, height, FilterType::Triangle);
+    }
+    if config.brightness != 0 {
+        img = img.brighten(config.brightness);
+    }
+    if config.contrast != 0.0 {
+        img = img.adjust_contrast(config.contrast);
+    }
+    if config.grayscale {
+        // `grayscale` keeps the alpha channel of RGBA captures, which OCR can't read as is.
+        img = DynamicImage::ImageLuma8(img.to_luma8());
+    }
 
-        Ok(img)
+    let level = match config.threshold {
+        ThresholdMode::None => return img,
+        ThresholdMode::Otsu => threshold::otsu_level(&img.to_luma8()),
+        ThresholdMode::Fixed(level) => level,
     };
+    DynamicImage::ImageLuma8(threshold::binarize(img.to_luma8(), level))
+}
 
-    if let Some(w) = Window::all().unwrap().iter().find(game_exist) {
-        get_image(w)
-    } else {
-        get_image(window)
+/// Grabs one frame in the configured pixel order.
+fn grab_frame(
+    source: &mut dyn ScreenSource,
+    capture: &CaptureConfig,
+) -> Result<DynamicImage, EncounterError> {
+    let img = reorder_pixels(source.grab()?, capture.pixel_order);
+    // Minimized windows capture as an empty image on some platforms.
+    if img.width() == 0 || img.height() == 0 {
+        return Err(EncounterError::capture(format!(
+            "captured an empty {}x{} frame",
+            img.width(),
+            img.height()
+        )));
     }
+    Ok(img)
 }
 
-pub fn encounter_process(
-    engine: &OcrEngine,
-    state: &mut EncounterState,
-    window: &Window,
-) -> Result<(), Box<dyn Error>> {
-    if matches!(state.mode, Mode::Init | Mode::Pause) {
-        return Ok(());
+/// The configured regions of a `width` x `height` frame, the top half when none are set.
+fn frame_regions(
+    capture: &CaptureConfig,
+    width: u32,
+    height: u32,
+) -> Result<Vec<CaptureRegion>, EncounterError> {
+    let factor = 0.5;
+
+    if capture.regions.is_empty() {
+        return Ok(vec![CaptureRegion {
+            x: 0,
+            y: 0,
+            width,
+            height: (height as f32 * factor) as u32,
+        }]);
+    }
+    for region in &capture.regions {
+        region.check_bounds(width, height)?;
+    }
+    Ok(capture.regions.clone())
+}
+
+/// Grabs one frame and returns every configured region of it, preprocessed, in config order.
+fn capture_screen(
+    debug: bool,
+    source: &mut dyn ScreenSource,
+    capture: &CaptureConfig,
+    preprocess_config: &PreprocessConfig,
+) -> Result<Vec<DynamicImage>, EncounterError> {
+    let img = grab_frame(source, capture)?;
+    let regions = frame_regions(capture, img.width(), img.height())?;
+
+    let crops = regions
+        .iter()
+        .map(|r| preprocess(img.crop_imm(r.x, r.y, r.width, r.height), preprocess_config))
+        .collect::<Vec<_>>();
+
+    if debug {
+        for (i, crop) in crops.iter().enumerate() {
+            match i {
+                0 => crop.save("debug.png")?,
+                _ => crop.save(format!("debug-{}.png", i))?,
+            }
+        }
+    }
+
+    Ok(crops)
+}
+
+/// Retries `capture_screen` with exponential backoff when the capture itself fails.
+fn capture_with_retry(
+    debug: bool,
+    source: &mut dyn ScreenSource,
+    capture: &CaptureConfig,
+    preprocess_config: &PreprocessConfig,
+) -> Result<Vec<DynamicImage>, EncounterError> {
+    let mut backoff = Duration::from_millis(capture.retry_backoff_ms);
+    let mut attempt = 0;
+
+    loop {
+        match capture_screen(debug, source, capture, preprocess_config) {
+            Err(EncounterError::CaptureFailed(err)) if attempt < capture.retries => {
+                warn!(
+                    "capture failed, retry {} of {} in {:?}: {}",
+                    attempt + 1,
+                    capture.retries,
+                    backoff,
+                    err
+                );
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
     }
+}
 
-    let mut mode_detect = Vec::with_capacity(ENCOUNTER_DETECT_FRAMES as usize);
-    for _ in 1..=ENCOUNTER_DETECT_FRAMES {
-        let buffer = capture_screen(state.debug, window)?;
-        let mons = get_mons(engine, buffer)?;
-        mode_detect.push(mons);
-        thread::sleep(Duration::from_millis(state.toggle.to_num()));
+/// Applies the most recent mode requested through `control`.
+fn apply_mode_requests(state: &mut EncounterState, control: &Receiver<Mode>) {
+    if let Some(mode) = control.try_iter().last() {
+        info!("mode {:?} requested in {:?}", mode, state.mode);
+        state.mode = mode;
     }
+}
 
+/// The OCR text each of `mons` was read from on `frames`, ignoring case. Names that were
+/// never read as such, e.g. typed in when confirming, are their own text.
+fn raw_text(frames: &[&FrameReading], mons: &[String]) -> Vec<String> {
+    mons.iter()
+        .map(|mon| {
+            let lowercase = mon.to_lowercase();
+            frames
+                .iter()
+                .flat_map(|frame| &frame.raw_text)
+                .find(|(name, _)| name.to_lowercase() == lowercase)
+                .map_or_else(|| mon.clone(), |(_, text)| text.clone())
+        })
+        .collect()
+}
+
+/// Moves the mode state machine forward with the mons detected on each frame of one cycle.
+fn apply_detection(
+    state: &mut EncounterState,
+    mode_detect: &[FrameReading],
+    config: &DetectionConfig,
+    filter: &dyn MonFilter,
+) -> Option<EncounterRecord> {
+    let on_reset_screen = mode_detect.iter().any(|frame| frame.reset_screen);
+    if on_reset_screen && !state.on_reset_screen {
+        state.resets += 1;
+        info!("soft reset {}", state.resets);
+    }
+    state.on_reset_screen = on_reset_screen;
+
+    let mut committed = None;
     match state.mode {
         Mode::Encounter => {
-            if mode_detect.iter().all(|(m, _)| m.is_empty()) {
+            let seen = mode_detect
+                .iter()
+                .filter(|frame| !frame.mons.is_empty())
+                .count() as u32;
+            // Manually added encounters have no frames to track.
+            let battle = state.history.last_mut().filter(|record| record.frames > 0);
+            if seen > 0 {
+                state.empty_cycles = 0;
+                if let Some(record) = battle {
+                    record.frames += seen;
+                }
+            } else if state.empty_cycles + 1 < config.end_encounter_cycles {
+                // OCR misses the names now and then mid-battle, e.g. during animations.
+                state.empty_cycles += 1;
+                debug!(
+                    "no mons read, {} of {} empty cycles before the battle ends",
+                    state.empty_cycles, config.end_encounter_cycles
+                );
+            } else {
+                info!("battle over, back to walk");
+                state.empty_cycles = 0;
                 state.mode = Mode::Walk;
-                state.lure_on = mode_detect.first().map_or(false, |(_, lure)| *lure);
+                state.lure_on = mode_detect.first().is_some_and(|frame| frame.lure_on);
+                if let Some(record) = battle {
+                    record.brief = record.frames < config.brief_encounter_frames;
+                }
             }
         }
         Mode::Walk => {
-            if let Some((mons, is_lure)) = mode_detect
+            // Mons on frames without the battle UI are menus or the dex, not an encounter.
+            let battle_frames = mode_detect
+                .iter()
+                .filter(|frame| frame.in_battle)
+                .collect::<Vec<_>>();
+            let ignored = mode_detect
                 .iter()
-                .filter(|(m, _)| !m.is_empty())
-                .max_by_key(|(m, _)| m.len())
+                .filter(|frame| !frame.in_battle && !frame.mons.is_empty())
+                .count();
+            if ignored > 0 {
+                debug!(
+                    "{} frames with mons but without the battle UI ignored",
+                    ignored
+                );
+            }
+            if let Some((mons, is_lure)) =
+                vote::merge_frames(&battle_frames, config.merge_distance, config.vote_share)
             {
-                state.encounters += mons.len() as u32;
-                state.last_encounter = mons.clone();
+                if let Some(expected) = config.expected_mons_per_encounter {
+                    if mons.len() != expected as usize {
+                        info!(
+                            "encounter with {:?} held back, expected {} mons",
+                            mons, expected
+                        );
+                        return None;
+                    }
+                }
+                let mons = state.stored_names(filter.review(mons));
+                if mons.is_empty() {
+                    info!("encounter rejected");
+                    return None;
+                }
+                info!("encounter with {:?}", mons);
                 state.mode = Mode::Encounter;
-                state.lure_on = *is_lure;
-
-                for mon in mons {
-                    *state.mon_stats.entry(mon.clone()).or_insert(0) += 1;
+                state.empty_cycles = 0;
+                state.lure_on = is_lure;
+                let frames = battle_frames
+                    .iter()
+                    .filter(|frame| !frame.mons.is_empty())
+                    .count() as u32;
+                let raw = raw_text(&battle_frames, &mons);
+                let record = state.count_battle(mons, raw, frames, config.new_species_encounters);
+                if matches!(config.mode, DetectionMode::Hatch { .. }) {
+                    state.eggs_hatched += 1;
                 }
+                // After counting, so a new species has its stats before the chain is kept in them.
+                state.update_chain(&record.mons);
+                committed = Some(record);
             }
         }
         _ => {}
     }
 
-    save_state(state)?;
+    committed
+}
+
+/// Replays the screenshots in `dir`, in file name order, through the detection state machine.
+/// Every `detect_frames` images make up one cycle. Starts in Walk when the state is in Init or Pause
+/// and never saves the state.
+pub fn replay_dir(
+    recognizer: &dyn MonRecognizer,
+    dir: &Path,
+    state: &mut EncounterState,
+    config: &DetectionConfig,
+    filter: &dyn MonFilter,
+) -> Result<Vec<EncounterRecord>, EncounterError> {
+    let frames = frame_paths(dir)?;
+
+    if matches!(state.mode, Mode::Init | Mode::Pause) {
+        state.mode = Mode::Walk;
+    }
+
+    let mut records = vec![];
+    for cycle in frames.chunks(config.detect_frames.max(1) as usize) {
+        let mut mode_detect = Vec::with_capacity(cycle.len());
+        for path in cycle {
+            let mut source = FileSource::new(path.clone());
+            let regions = capture_screen(false, &mut source, &config.capture, &config.preprocess)?;
+            mode_detect.push(recognizer.recognize(regions, config.line_padding, filter)?);
+        }
+        records.extend(apply_detection(state, &mode_detect, config, filter));
+    }
+
+    Ok(records)
+}
+
+/// Reads every screenshot in `dir` `rounds` times and sums the time each stage took, with
+/// the number of frames read. Capture is loading and preprocessing a file here. Nothing is
+/// counted, so repeated runs over the same folder can be compared, see `--bench`.
+pub fn bench_dir(
+    recognizer: &dyn MonRecognizer,
+    dir: &Path,
+    rounds: u32,
+    config: &DetectionConfig,
+    filter: &dyn MonFilter,
+) -> Result<(u32, StageTimings), EncounterError> {
+    let frames = frame_paths(dir)?;
+    let mut timings = StageTimings::default();
+    let mut read = 0;
+    for _ in 0..rounds {
+        for path in &frames {
+            let started = Instant::now();
+            let mut source = FileSource::new(path.clone());
+            let regions = capture_screen(false, &mut source, &config.capture, &config.preprocess)?;
+            timings.capture += started.elapsed();
+            timings += recognizer
+                .recognize(regions, config.line_padding, filter)?
+                .timings;
+            read += 1;
+        }
+    }
+    Ok((read, timings))
+}
+
+/// The screenshots in `dir`, in file name order.
+fn frame_paths(dir: &Path) -> Result<Vec<PathBuf>, EncounterError> {
+    let mut frames = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    frames.retain(|path| {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+    });
+    frames.sort();
+    Ok(frames)
+}
+
+/// Runs the frames of a recorded video through the detection state machine until it ends,
+/// `detect_frames` sampled frames make up one cycle. Starts in Walk like `replay_dir`
+/// and never saves the state.
+#[cfg(feature = "video")]
+pub fn replay_video(
+    recognizer: &dyn MonRecognizer,
+    source: &mut VideoSource,
+    state: &mut EncounterState,
+    config: &DetectionConfig,
+    filter: &dyn MonFilter,
+) -> Result<Vec<EncounterRecord>, EncounterError> {
+    if matches!(state.mode, Mode::Init | Mode::Pause) {
+        state.mode = Mode::Walk;
+    }
+
+    let mut records = vec![];
+    while !source.finished() {
+        let mut mode_detect = Vec::with_capacity(config.detect_frames.max(1) as usize);
+        for _ in 0..config.detect_frames.max(1) {
+            match capture_screen(false, source, &config.capture, &config.preprocess) {
+                Ok(regions) => {
+                    mode_detect.push(recognizer.recognize(regions, config.line_padding, filter)?)
+                }
+                Err(_) if source.finished() => break,
+                Err(err) => return Err(err),
+            }
+        }
+        if !mode_detect.is_empty() {
+            records.extend(apply_detection(state, &mode_detect, config, filter));
+        }
+    }
+
+    Ok(records)
+}
+
+/// Saves every region of every frame in `frames` as `<encounter>-<mons>-<frame>-<region>.png`.
+fn save_encounter_frames(
+    dir: &Path,
+    record: &EncounterRecord,
+    frames: &[Vec<DynamicImage>],
+) -> Result<(), EncounterError> {
+    fs::create_dir_all(dir)?;
+    let mons = record
+        .mons
+        .join("_")
+        .replace(|c: char| !c.is_alphanumeric() && c != '_', "-");
+    for (i, regions) in frames.iter().enumerate() {
+        for (j, region) in regions.iter().enumerate() {
+            let name = format!("{}-{}-{}-{}.png", record.encounter, mons, i + 1, j + 1);
+            region.save(dir.join(name))?;
+        }
+    }
     Ok(())
 }
+
+/// Runs one detection cycle and returns the encounter it counted, if any.
+///
+/// Modes sent to `control` are applied at the start of the cycle and between frames,
+/// a Pause or Init request ends the cycle right away.
+pub fn encounter_process(
+    recognizer: &dyn MonRecognizer,
+    state: &mut EncounterState,
+    source: &mut dyn ScreenSource,
+    config: &DetectionConfig,
+    filter: &dyn MonFilter,
+    store: &dyn StateStore,
+    control: &Receiver<Mode>,
+) -> Result<Option<EncounterRecord>, EncounterError> {
+    apply_mode_requests(state, control);
+    state.track_active_time();
+    if matches!(state.mode, Mode::Init | Mode::Pause) {
+        return Ok(None);
+    }
+
+    let mut cycle = Cycle::default();
+    for _ in 1..=config.detect_frames.max(1) {
+        let started = Instant::now();
+        let regions = match cycle.capture(state, source, config) {
+            Err(err @ EncounterError::CaptureTimedOut(_)) => {
+                warn!("cycle skipped: {}", err);
+                return Ok(None);
+            }
+            captured => captured?,
+        };
+        match recognizer.recognize(regions, config.line_padding, filter) {
+            Ok(reading) => cycle.push(reading),
+            Err(err @ EncounterError::OcrTimedOut(_)) => {
+                warn!("cycle skipped: {}", err);
+                return Ok(None);
+            }
+            Err(err) => return Err(err),
+        }
+        thread::sleep(frame_delay(state, config, started));
+
+        apply_mode_requests(state, control);
+        if matches!(state.mode, Mode::Init | Mode::Pause) {
+            return Ok(None);
+        }
+    }
+
+    cycle.finish(state, config, filter, store)
+}
+
+/// Wait after a frame captured at `started`. With `max_fps` set it's what is left of the frame
+/// period, so time spent on capture and OCR counts towards it.
+fn frame_delay(state: &EncounterState, config: &DetectionConfig, started: Instant) -> Duration {
+    match config.max_fps.filter(|fps| *fps > 0.0) {
+        Some(fps) => Duration::from_secs_f64(1.0 / fps).saturating_sub(started.elapsed()),
+        None => Duration::from_millis(config.sleep_ms.unwrap_or_else(|| state.toggle.to_num())),
+    }
+}
+
+/// The frames of one detection cycle read so far.
+#[derive(Default)]
+struct Cycle {
+    readings: Vec<FrameReading>,
+    /// Regions of every frame, only kept for `save_encounter_frames`.
+    frames: Vec<Vec<DynamicImage>>,
+    timings: StageTimings,
+}
+
+impl Cycle {
+    /// Captures the regions of the next frame.
+    fn capture(
+        &mut self,
+        state: &EncounterState,
+        source: &mut dyn ScreenSource,
+        config: &DetectionConfig,
+    ) -> Result<Vec<DynamicImage>, EncounterError> {
+        let started = Instant::now();
+        let regions = capture_with_retry(state.debug, source, &config.capture, &config.preprocess)?;
+        self.timings.capture += started.elapsed();
+        if config.save_encounter_frames.is_some() {
+            self.frames.push(regions.clone());
+        }
+        Ok(regions)
+    }
+
+    fn push(&mut self, reading: FrameReading) {
+        self.timings += reading.timings;
+        self.readings.push(reading);
+    }
+
+    /// Moves the mode state machine forward with the frames read, writes out the encounter
+    /// this commits and saves the state.
+    fn finish(
+        self,
+        state: &mut EncounterState,
+        config: &DetectionConfig,
+        filter: &dyn MonFilter,
+        store: &dyn StateStore,
+    ) -> Result<Option<EncounterRecord>, EncounterError> {
+        if config.log_timings {
+            info!(
+                "cycle of {} frames took {}",
+                self.readings.len(),
+                self.timings
+            );
+        }
+
+        // Names lingering after a battle must not count as the next encounter.
+        let cooling_down = state.mode == Mode::Walk
+            && unix_millis().saturating_sub(state.last_encounter_ms) < config.encounter_cooldown_ms;
+        let committed = if cooling_down {
+            debug!("cycle skipped, encounter cooldown still running");
+            None
+        } else {
+            apply_detection(state, &self.readings, config, filter)
+        };
+        if let Some(record) = &committed {
+            state.last_encounter_ms = unix_millis();
+            if let Some(path) = &config.event_log {
+                record.append_to(path)?;
+            }
+            if let Some(dir) = &config.save_encounter_frames {
+                save_encounter_frames(dir, record, &self.frames)?;
+            }
+        }
+
+        store.save(state)?;
+        Ok(committed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GrayImage, ImageBuffer, Rgb, RgbaImage};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::sync::mpsc;
+
+    #[test]
+    fn frame_bytes_reads_gray_in_place() {
+        let frame = DynamicImage::ImageLuma8(GrayImage::from_raw(2, 1, vec![10, 20]).unwrap());
+        let bytes = frame_bytes(&frame);
+        assert!(matches!(bytes, Cow::Borrowed(_)));
+        assert_eq!(&*bytes, &[10, 20]);
+    }
+
+    #[test]
+    fn frame_bytes_reads_rgb_in_place() {
+        let frame =
+            DynamicImage::ImageRgb8(RgbImage::from_raw(2, 1, vec![1, 2, 3, 4, 5, 6]).unwrap());
+        let bytes = frame_bytes(&frame);
+        assert!(matches!(bytes, Cow::Borrowed(_)));
+        assert_eq!(&*bytes, &[1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn frame_bytes_reads_rgba_in_place() {
+        let frame = DynamicImage::ImageRgba8(
+            RgbaImage::from_raw(2, 1, vec![1, 2, 3, 255, 4, 5, 6, 128]).unwrap(),
+        );
+        let bytes = frame_bytes(&frame);
+        assert!(matches!(bytes, Cow::Borrowed(_)));
+        assert_eq!(bytes.len(), 2 * 4);
+        assert_eq!(&*bytes, &[1, 2, 3, 255, 4, 5, 6, 128]);
+    }
+
+    #[test]
+    fn frame_bytes_converts_rgb16_to_rgb8() {
+        let pixels: ImageBuffer<Rgb<u16>, Vec<u16>> =
+            ImageBuffer::from_raw(2, 1, vec![0, 32896, 65535, 65535, 0, 257]).unwrap();
+        let frame = DynamicImage::ImageRgb16(pixels);
+        let bytes = frame_bytes(&frame);
+        assert!(matches!(bytes, Cow::Owned(_)));
+        assert_eq!(bytes.len(), 2 * 3);
+        assert_eq!(&*bytes, &[0, 128, 255, 255, 0, 1]);
+    }
+
+    /// Times reading a full HD capture in place against the `to_rgb8()` copy `prepare_frame`
+    /// used to make. Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn frame_bytes_beats_rgb_copy() {
+        const RUNS: u32 = 50;
+        let frame = DynamicImage::ImageRgba8(RgbaImage::new(1920, 1080));
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            std::hint::black_box(frame_bytes(std::hint::black_box(&frame)));
+        }
+        let in_place = start.elapsed() / RUNS;
+        let start = Instant::now();
+        for _ in 0..RUNS {
+            std::hint::black_box(std::hint::black_box(&frame).to_rgb8().into_raw());
+        }
+        let copied = start.elapsed() / RUNS;
+        println!("in place {:?}, to_rgb8 {:?} per frame", in_place, copied);
+        assert!(in_place < copied);
+    }
+
+    /// What every capture below holds once in RGBA.
+    const REORDERED: [u8; 8] = [10, 20, 30, 255, 40, 50, 60, 128];
+
+    #[test]
+    fn reorder_pixels_keeps_rgba() {
+        let frame = DynamicImage::ImageRgba8(
+            RgbaImage::from_raw(2, 1, vec![10, 20, 30, 255, 40, 50, 60, 128]).unwrap(),
+        );
+        assert_eq!(
+            reorder_pixels(frame, PixelOrder::Rgba).as_bytes(),
+            &REORDERED
+        );
+    }
+
+    #[test]
+    fn reorder_pixels_swaps_bgra() {
+        let frame = DynamicImage::ImageRgba8(
+            RgbaImage::from_raw(2, 1, vec![30, 20, 10, 255, 60, 50, 40, 128]).unwrap(),
+        );
+        assert_eq!(
+            reorder_pixels(frame, PixelOrder::Bgra).as_bytes(),
+            &REORDERED
+        );
+    }
+
+    #[test]
+    fn reorder_pixels_moves_alpha_of_argb() {
+        let frame = DynamicImage::ImageRgba8(
+            RgbaImage::from_raw(2, 1, vec![255, 10, 20, 30, 128, 40, 50, 60]).unwrap(),
+        );
+        assert_eq!(
+            reorder_pixels(frame, PixelOrder::Argb).as_bytes(),
+            &REORDERED
+        );
+    }
+
+    #[test]
+    fn reorder_pixels_reverses_abgr() {
+        let frame = DynamicImage::ImageRgba8(
+            RgbaImage::from_raw(2, 1, vec![255, 30, 20, 10, 128, 60, 50, 40]).unwrap(),
+        );
+        assert_eq!(
+            reorder_pixels(frame, PixelOrder::Abgr).as_bytes(),
+            &REORDERED
+        );
+    }
+
+    #[test]
+    fn reorder_pixels_leaves_other_layouts() {
+        let frame = DynamicImage::ImageRgb8(RgbImage::from_raw(1, 1, vec![30, 20, 10]).unwrap());
+        assert_eq!(
+            reorder_pixels(frame, PixelOrder::Bgra).as_bytes(),
+            &[30, 20, 10]
+        );
+    }
+
+    /// Hands out `readings` one frame at a time, then frames without any text.
+    struct ScriptedRecognizer {
+        readings: RefCell<VecDeque<FrameReading>>,
+    }
+
+    impl ScriptedRecognizer {
+        fn new(readings: Vec<FrameReading>) -> Self {
+            Self {
+                readings: RefCell::new(readings.into()),
+            }
+        }
+    }
+
+    impl MonRecognizer for ScriptedRecognizer {
+        fn recognize(
+            &self,
+            _regions: Vec<DynamicImage>,
+            _line_padding: u32,
+            _filter: &dyn MonFilter,
+        ) -> Result<FrameReading, EncounterError> {
+            Ok(self.readings.borrow_mut().pop_front().unwrap_or_default())
+        }
+    }
+
+    struct BlankSource;
+
+    impl ScreenSource for BlankSource {
+        fn grab(&mut self) -> Result<DynamicImage, EncounterError> {
+            Ok(DynamicImage::new_rgba8(4, 4))
+        }
+    }
+
+    struct NoStore;
+
+    impl StateStore for NoStore {
+        fn load(&self) -> Result<EncounterState, EncounterError> {
+            Ok(EncounterState::default())
+        }
+
+        fn save(&self, _state: &EncounterState) -> Result<(), EncounterError> {
+            Ok(())
+        }
+    }
+
+    fn battle(mons: &[&str]) -> FrameReading {
+        FrameReading {
+            mons: mons.iter().map(|mon| mon.to_string()).collect(),
+            in_battle: true,
+            ..Default::default()
+        }
+    }
+
+    fn nothing() -> FrameReading {
+        FrameReading::default()
+    }
+
+    /// One frame per cycle and no waiting between them.
+    fn config() -> DetectionConfig {
+        DetectionConfig {
+            sleep_ms: Some(0),
+            detect_frames: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Runs one cycle per reading and returns the mode after every cycle and what it counted.
+    fn run(
+        state: &mut EncounterState,
+        config: &DetectionConfig,
+        readings: Vec<FrameReading>,
+    ) -> Vec<(Mode, Option<EncounterRecord>)> {
+        let cycles = readings.len();
+        let recognizer = ScriptedRecognizer::new(readings);
+        let filter = DefaultMonFilter::new(config);
+        let (_sender, control) = mpsc::channel();
+        (0..cycles)
+            .map(|_| {
+                let committed = encounter_process(
+                    &recognizer,
+                    state,
+                    &mut BlankSource,
+                    config,
+                    &filter,
+                    &NoStore,
+                    &control,
+                )
+                .unwrap();
+                (state.mode.clone(), committed)
+            })
+            .collect()
+    }
+
+    fn walking() -> EncounterState {
+        EncounterState {
+            mode: Mode::Walk,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn battle_goes_from_walk_to_encounter_and_back() {
+        let mut state = walking();
+        let cycles = run(
+            &mut state,
+            &config(),
+            vec![
+                nothing(),
+                battle(&["Pidgey"]),
+                battle(&["Pidgey"]),
+                nothing(),
+                battle(&["Rattata"]),
+            ],
+        );
+
+        let modes = cycles
+            .iter()
+            .map(|(mode, _)| mode.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            modes,
+            [
+                Mode::Walk,
+                Mode::Encounter,
+                Mode::Encounter,
+                Mode::Walk,
+                Mode::Encounter
+            ]
+        );
+        let counted = cycles
+            .iter()
+            .map(|(_, committed)| committed.as_ref().map(|record| record.mons.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            counted,
+            [
+                None,
+                Some(vec!["Pidgey".to_string()]),
+                None,
+                None,
+                Some(vec!["Rattata".to_string()])
+            ]
+        );
+        assert_eq!(state.encounters, 2);
+        assert_eq!(state.battle_count, 2);
+    }
+
+    #[test]
+    fn long_battle_is_counted_once() {
+        let mut state = walking();
+        let mut readings = vec![battle(&["Zubat", "Zubat"]); 5];
+        readings.push(nothing());
+        run(&mut state, &config(), readings);
+
+        assert_eq!(state.mode, Mode::Walk);
+        assert_eq!(state.history.len(), 1);
+        assert_eq!(state.history[0].frames, 5);
+        assert_eq!(state.encounters, 2);
+        assert_eq!(state.battle_count, 1);
+        assert_eq!(state.mon_stats["Zubat"].count, 2);
+    }
+
+    #[test]
+    fn names_after_a_battle_wait_for_the_cooldown() {
+        let mut state = walking();
+        let config = DetectionConfig {
+            encounter_cooldown_ms: 60_000,
+            ..config()
+        };
+        let cycles = run(
+            &mut state,
+            &config,
+            vec![battle(&["Pidgey"]), nothing(), battle(&["Pidgey"])],
+        );
+
+        assert_eq!(cycles[2], (Mode::Walk, None));
+        assert_eq!(state.encounters, 1);
+
+        state.last_encounter_ms = 0;
+        let cycles = run(&mut state, &config, vec![battle(&["Pidgey"])]);
+        assert_eq!(cycles[0].0, Mode::Encounter);
+        assert_eq!(state.encounters, 2);
+    }
+
+    /// Holds a hunt that was paused when it was saved.
+    struct PausedStore;
+
+    impl StateStore for PausedStore {
+        fn load(&self) -> Result<EncounterState, EncounterError> {
+            Ok(EncounterState {
+                mode: Mode::Pause,
+                encounters: 3,
+                ..Default::default()
+            })
+        }
+
+        fn save(&self, _state: &EncounterState) -> Result<(), EncounterError> {
+            Ok(())
+        }
+    }
+
+    struct CorruptStore;
+
+    impl StateStore for CorruptStore {
+        fn load(&self) -> Result<EncounterState, EncounterError> {
+            Err(serde_json::from_str::<EncounterState>("{")
+                .unwrap_err()
+                .into())
+        }
+
+        fn save(&self, _state: &EncounterState) -> Result<(), EncounterError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tracker_starts_from_the_stored_hunt_in_init() {
+        let tracker = Tracker::new(config(), Box::new(BlankSource), Box::new(PausedStore)).unwrap();
+        assert_eq!(tracker.state.mode, Mode::Init);
+        assert_eq!(tracker.state.encounters, 3);
+    }
+
+    #[test]
+    fn tracker_fails_on_an_unreadable_state() {
+        assert!(Tracker::new(config(), Box::new(BlankSource), Box::new(CorruptStore)).is_err());
+    }
+
+    #[test]
+    fn route_and_method_totals_follow_held_back_species() {
+        let mut state = EncounterState::default();
+        state.set_route("Route 1");
+        state.set_method("grass");
+        let mons =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|name| name.to_string()).collect() };
+        state.count_battle(mons(&["Oddish", "Pidgey"]), vec![], 1, 2);
+        state.count_battle(mons(&["Oddish"]), vec![], 1, 2);
+        state.count_battle(mons(&[]), vec![], 1, 2);
+
+        assert_eq!(state.mon_stats["Oddish"].count, 2);
+        let species_total = state.mon_stats.values().map(|stat| stat.count).sum::<u32>();
+        for stats in [
+            state.route_stats().unwrap(),
+            state.current_method_stats().unwrap(),
+        ] {
+            assert_eq!(stats.encounters, state.encounters);
+            assert_eq!(stats.battle_count, state.battle_count);
+            assert_eq!(stats.mon_counts.values().sum::<u32>(), species_total);
+            assert_eq!(stats.mon_counts["Oddish"], 2);
+        }
+    }
+
+    #[test]
+    fn bench_sums_every_frame_of_every_round() {
+        let dir = std::env::temp_dir().join("rencounter-bench");
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["a.png", "b.png"] {
+            RgbaImage::new(4, 4).save(dir.join(name)).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "not a frame").unwrap();
+        let reading = || FrameReading {
+            timings: StageTimings {
+                recognize: Duration::from_millis(5),
+                ..StageTimings::default()
+            },
+            ..FrameReading::default()
+        };
+        let recognizer = ScriptedRecognizer::new((0..4).map(|_| reading()).collect());
+        let config = config();
+        let filter = DefaultMonFilter::new(&config);
+
+        let (frames, timings) = bench_dir(&recognizer, &dir, 2, &config, &filter).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(frames, 4);
+        assert_eq!(timings.recognize, Duration::from_millis(20));
+        assert_eq!(
+            timings.per_frame(frames).recognize,
+            Duration::from_millis(5)
+        );
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn padding_keeps_the_angle_of_a_skewed_word() {
+        let up = rten_imageproc::Vec2::from_xy(0.6, -0.8);
+        let word = RotatedRect::new(Point::from_yx(50., 50.), up, 20., 10.);
+
+        let padded = pad_word(&word, 2., (100., 100.));
+        assert!(close(padded.width(), 24.) && close(padded.height(), 14.));
+        assert!(close(padded.up_axis().x, 0.6) && close(padded.up_axis().y, -0.8));
+        assert!(close(padded.center().x, 50.) && close(padded.center().y, 50.));
+    }
+
+    #[test]
+    fn padding_stops_at_the_edge_of_the_image() {
+        let word = RotatedRect::from_rect(rten_imageproc::Rect::from_tlbr(0., 5., 10., 20.));
+
+        let padded = pad_word(&word, 3., (22., 100.)).bounding_rect();
+        assert!(close(padded.top(), 0.) && close(padded.bottom(), 13.));
+        assert!(close(padded.left(), 2.) && close(padded.right(), 22.));
+    }
+}
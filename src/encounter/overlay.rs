@@ -0,0 +1,173 @@
+use image::{DynamicImage, Rgb, RgbImage};
+use ocrs::{OcrEngine, TextItem};
+use std::path::Path;
+
+use super::{
+    frame_regions, grab_frame, pad_lines, prepare_frame, CaptureConfig, CaptureRegion,
+    EncounterError, ScreenSource,
+};
+
+const WORD_COLOR: Rgb<u8> = Rgb([0, 120, 255]);
+const LINE_COLOR: Rgb<u8> = Rgb([0, 200, 0]);
+const TEXT_COLOR: Rgb<u8> = Rgb([255, 0, 0]);
+const REGION_COLOR: Rgb<u8> = Rgb([255, 0, 255]);
+
+/// Axis aligned box in pixels of the OCR'd image, `right` and `bottom` are exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextBox {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl TextBox {
+    pub(super) fn union(self, other: TextBox) -> TextBox {
+        TextBox {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+}
+
+/// Intermediate results of one OCR pass, to see whether cropping, text detection or the
+/// filters are at fault.
+#[derive(Debug, Clone, Default)]
+pub struct OcrTrace {
+    /// Every word the detection model found.
+    pub word_rects: Vec<TextBox>,
+    /// The words grouped into lines.
+    pub line_rects: Vec<TextBox>,
+    /// Recognized text of each line that produced any.
+    pub lines: Vec<(String, TextBox)>,
+}
+
+impl OcrTrace {
+    /// Outlines words in blue, lines in green and recognized text in red.
+    pub fn draw(&self, img: &mut RgbImage) {
+        for rect in &self.word_rects {
+            draw_outline(img, rect, WORD_COLOR);
+        }
+        for rect in &self.line_rects {
+            draw_outline(img, rect, LINE_COLOR);
+        }
+        for (_, rect) in &self.lines {
+            draw_outline(img, rect, TEXT_COLOR);
+        }
+    }
+}
+
+/// Runs the same OCR pass as detection, with lines padded by `line_padding`, but keeps every
+/// intermediate step.
+pub fn trace_ocr(
+    engine: &OcrEngine,
+    data: &DynamicImage,
+    line_padding: u32,
+) -> Result<OcrTrace, EncounterError> {
+    let ocr_input = prepare_frame(engine, data)?;
+    let word_rects = engine
+        .detect_words(&ocr_input)
+        .map_err(EncounterError::ocr)?;
+    let line_rects = pad_lines(
+        engine.find_text_lines(&ocr_input, &word_rects),
+        line_padding,
+        data.width(),
+        data.height(),
+    );
+    let line_texts = engine
+        .recognize_text(&ocr_input, &line_rects)
+        .map_err(EncounterError::ocr)?;
+
+    let to_box = |rect: &rten_imageproc::RotatedRect| {
+        let rect = rect.bounding_rect();
+        TextBox {
+            left: rect.left().floor() as i32,
+            top: rect.top().floor() as i32,
+            right: rect.right().ceil() as i32,
+            bottom: rect.bottom().ceil() as i32,
+        }
+    };
+
+    Ok(OcrTrace {
+        word_rects: word_rects.iter().map(to_box).collect(),
+        line_rects: line_rects
+            .iter()
+            .filter_map(|line| line.iter().map(to_box).reduce(TextBox::union))
+            .collect(),
+        lines: line_texts
+            .iter()
+            .flatten()
+            .map(|line| {
+                let rect = line.bounding_rect();
+                let text_box = TextBox {
+                    left: rect.left(),
+                    top: rect.top(),
+                    right: rect.right(),
+                    bottom: rect.bottom(),
+                };
+                (line.to_string(), text_box)
+            })
+            .collect(),
+    })
+}
+
+fn draw_outline(img: &mut RgbImage, rect: &TextBox, color: Rgb<u8>) {
+    let (width, height) = (img.width() as i32, img.height() as i32);
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    let left = rect.left.clamp(0, width - 1);
+    let right = (rect.right - 1).clamp(0, width - 1);
+    let top = rect.top.clamp(0, height - 1);
+    let bottom = (rect.bottom - 1).clamp(0, height - 1);
+    if left > right || top > bottom {
+        return;
+    }
+
+    for x in left..=right {
+        img.put_pixel(x as u32, top as u32, color);
+        img.put_pixel(x as u32, bottom as u32, color);
+    }
+    for y in top..=bottom {
+        img.put_pixel(left as u32, y as u32, color);
+        img.put_pixel(right as u32, y as u32, color);
+    }
+}
+
+/// Grabs one full frame from `source`, outlines every region `capture` would hand to OCR and
+/// saves it to `out_path`, to check that the regions cover the level text. Returns the regions.
+pub fn debug_region_overlay(
+    source: &mut dyn ScreenSource,
+    capture: &CaptureConfig,
+    out_path: &Path,
+) -> Result<Vec<CaptureRegion>, EncounterError> {
+    let frame = grab_frame(source, capture)?;
+    let regions = frame_regions(capture, frame.width(), frame.height())?;
+
+    let mut img = frame.to_rgb8();
+    for region in &regions {
+        let rect = TextBox {
+            left: region.x as i32,
+            top: region.y as i32,
+            right: region.x.saturating_add(region.width) as i32,
+            bottom: region.y.saturating_add(region.height) as i32,
+        };
+        // Two pixels wide, one is easy to miss on a full screenshot.
+        draw_outline(&mut img, &rect, REGION_COLOR);
+        draw_outline(
+            &mut img,
+            &TextBox {
+                left: rect.left + 1,
+                top: rect.top + 1,
+                right: rect.right - 1,
+                bottom: rect.bottom - 1,
+            },
+            REGION_COLOR,
+        );
+    }
+    img.save(out_path)?;
+    Ok(regions)
+}
@@ -0,0 +1,174 @@
+use image::DynamicImage;
+use ocrs::OcrEngine;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::{get_mons, read_regions, reading_from_lines, EncounterError, MonFilter, StageTimings};
+
+/// Side of the thumbnail every region is reduced to before frames are compared.
+const FINGERPRINT_SIZE: u32 = 16;
+
+/// What was read on a single frame.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameReading {
+    pub mons: Vec<String>,
+    /// OCR text every name of `mons` was read from, before normalizing and the dictionary.
+    pub raw_text: HashMap<String, String>,
+    pub lure_on: bool,
+    /// Whether the frame shows the battle UI, see `MonFilter::confirms_battle`.
+    pub in_battle: bool,
+    /// Whether the frame shows a soft reset, see `MonFilter::shows_reset`.
+    pub reset_screen: bool,
+    /// Time OCR took on the frame, zero when it was skipped.
+    pub timings: StageTimings,
+}
+
+/// Turns the preprocessed regions of a frame into the mons on them and whether a lure is active.
+/// Text lines are grown by `line_padding` pixels before they are read, see
+/// `DetectionConfig::line_padding`. Detection only talks to this trait, so a scripted
+/// recognizer can drive the mode state machine without OCR models or a screen.
+pub trait MonRecognizer {
+    fn recognize(
+        &self,
+        regions: Vec<DynamicImage>,
+        line_padding: u32,
+        filter: &dyn MonFilter,
+    ) -> Result<FrameReading, EncounterError>;
+}
+
+impl MonRecognizer for OcrEngine {
+    fn recognize(
+        &self,
+        regions: Vec<DynamicImage>,
+        line_padding: u32,
+        filter: &dyn MonFilter,
+    ) -> Result<FrameReading, EncounterError> {
+        get_mons(self, regions, line_padding, filter)
+    }
+}
+
+/// Runs OCR on a thread of its own and gives up on frames that take longer than `timeout`,
+/// so a stuck OCR call can't hang detection. The stuck thread is left behind and the next
+/// frame gets a new one. Without a timeout OCR runs on the calling thread. Captures are bounded
+/// by `TimedSource`.
+pub struct TimedOcr {
+    engine: Arc<OcrEngine>,
+    timeout: Option<Duration>,
+}
+
+impl TimedOcr {
+    pub fn new(engine: OcrEngine, timeout: Option<Duration>) -> Self {
+        Self {
+            engine: Arc::new(engine),
+            timeout,
+        }
+    }
+}
+
+impl MonRecognizer for TimedOcr {
+    fn recognize(
+        &self,
+        regions: Vec<DynamicImage>,
+        line_padding: u32,
+        filter: &dyn MonFilter,
+    ) -> Result<FrameReading, EncounterError> {
+        let Some(timeout) = self.timeout else {
+            return get_mons(&self.engine, regions, line_padding, filter);
+        };
+
+        let engine = Arc::clone(&self.engine);
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = sender.send(read_regions(&engine, &regions, line_padding));
+        });
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => {
+                let (lines, timings) = result?;
+                Ok(FrameReading {
+                    timings,
+                    ..reading_from_lines(lines, filter)
+                })
+            }
+            Err(RecvTimeoutError::Timeout) => Err(EncounterError::OcrTimedOut(timeout)),
+            Err(RecvTimeoutError::Disconnected) => Err(EncounterError::ocr("OCR thread panicked")),
+        }
+    }
+}
+
+/// Skips OCR on frames that barely changed since the last recognized one and returns the
+/// last reading instead, so a long walk doesn't run OCR on every cycle.
+pub struct SkipStaticFrames<R> {
+    inner: R,
+    /// Mean difference per pixel, in percent, up to which a frame counts as unchanged.
+    /// 0 recognizes every frame.
+    threshold: f32,
+    last: RefCell<Option<(Vec<u8>, FrameReading)>>,
+}
+
+impl<R: MonRecognizer> SkipStaticFrames<R> {
+    pub fn new(inner: R, threshold: f32) -> Self {
+        Self {
+            inner,
+            threshold,
+            last: RefCell::new(None),
+        }
+    }
+
+    fn unchanged(&self, fingerprint: &[u8]) -> Option<FrameReading> {
+        let last = self.last.borrow();
+        let (last_fingerprint, reading) = last.as_ref()?;
+        if last_fingerprint.len() != fingerprint.len() || fingerprint.is_empty() {
+            return None;
+        }
+
+        let total = last_fingerprint
+            .iter()
+            .zip(fingerprint)
+            .map(|(a, b)| a.abs_diff(*b) as f32)
+            .sum::<f32>();
+        let difference = total / fingerprint.len() as f32 / 255.0 * 100.0;
+        (difference <= self.threshold).then(|| FrameReading {
+            timings: StageTimings::default(),
+            ..reading.clone()
+        })
+    }
+}
+
+/// Tiny grayscale copy of every region, cheap to compare between frames.
+fn fingerprint(regions: &[DynamicImage]) -> Vec<u8> {
+    regions
+        .iter()
+        .flat_map(|region| {
+            region
+                .thumbnail_exact(FINGERPRINT_SIZE, FINGERPRINT_SIZE)
+                .to_luma8()
+                .into_raw()
+        })
+        .collect()
+}
+
+impl<R: MonRecognizer> MonRecognizer for SkipStaticFrames<R> {
+    fn recognize(
+        &self,
+        regions: Vec<DynamicImage>,
+        line_padding: u32,
+        filter: &dyn MonFilter,
+    ) -> Result<FrameReading, EncounterError> {
+        if self.threshold <= 0.0 {
+            return self.inner.recognize(regions, line_padding, filter);
+        }
+
+        let fingerprint = fingerprint(&regions);
+        if let Some(reading) = self.unchanged(&fingerprint) {
+            log::debug!("frame unchanged, OCR skipped");
+            return Ok(reading);
+        }
+        let reading = self.inner.recognize(regions, line_padding, filter)?;
+        *self.last.borrow_mut() = Some((fingerprint, reading.clone()));
+        Ok(reading)
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use super::{EncounterState, MonFilter, MonStat};
+
+impl EncounterState {
+    /// Rebuilds `mon_stats`, `encounters` and the mons of every record in `history` by running
+    /// the OCR text kept in `history` through `filter` again, e.g. after the dictionary or the
+    /// normalize rules changed. Names `filter` no longer counts are dropped, their battles still
+    /// count as one encounter. Encounters from before `history` was kept can't be read again,
+    /// they stay in the total and in the counts of their species. Routes and methods keep their
+    /// counts, records don't say where they were counted.
+    pub fn recompute_stats(&mut self, filter: &dyn MonFilter) {
+        let Some(first) = self.history.first() else {
+            return;
+        };
+        let mut encounters = first
+            .encounter
+            .saturating_sub(first.mons.len().max(1) as u32);
+
+        let mut logged: HashMap<&str, u32> = HashMap::new();
+        for mon in self.history.iter().flat_map(|record| &record.mons) {
+            *logged.entry(mon).or_default() += 1;
+        }
+        let mut mon_stats = self
+            .mon_stats
+            .iter()
+            .filter_map(|(name, stat)| {
+                let untracked = stat
+                    .count
+                    .saturating_sub(logged.get(name.as_str()).copied().unwrap_or(0));
+                let first_seen = if stat.first_seen <= encounters {
+                    stat.first_seen
+                } else {
+                    0
+                };
+                (untracked > 0).then(|| {
+                    let stat = MonStat {
+                        count: untracked,
+                        first_seen,
+                        last_seen: first_seen,
+                        longest_chain: stat.longest_chain,
+                    };
+                    (name.clone(), stat)
+                })
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut chain = (String::new(), 0);
+        for record in &mut self.history {
+            let raw = if record.raw.len() == record.mons.len() {
+                &record.raw
+            } else {
+                &record.mons
+            };
+            // Keep one spelling per species, like counting does.
+            let mons = raw
+                .iter()
+                .filter_map(|text| filter.recheck(text))
+                .map(|mon| {
+                    let lowercase = mon.to_lowercase();
+                    mon_stats
+                        .keys()
+                        .find(|species| species.to_lowercase() == lowercase)
+                        .cloned()
+                        .unwrap_or(mon)
+                })
+                .collect::<Vec<_>>();
+
+            let mut new_species: Vec<String> = vec![];
+            for (mon, encounter) in mons.iter().zip(encounters + 1..) {
+                if !mon_stats.contains_key(mon) && !new_species.contains(mon) {
+                    new_species.push(mon.clone());
+                }
+                let stat = mon_stats.entry(mon.clone()).or_default();
+                if stat.first_seen == 0 {
+                    stat.first_seen = encounter;
+                }
+                stat.last_seen = encounter;
+                stat.count += 1;
+            }
+            encounters += mons.len().max(1) as u32;
+
+            chain = match mons.split_first() {
+                Some((first, rest)) if rest.iter().all(|mon| mon == first) => {
+                    let length = if chain.0 == *first { chain.1 + 1 } else { 1 };
+                    (first.clone(), length)
+                }
+                _ => (String::new(), 0),
+            };
+            if let Some(stat) = mon_stats.get_mut(&chain.0) {
+                stat.longest_chain = stat.longest_chain.max(chain.1);
+            }
+
+            record.encounter = encounters;
+            record.new_species = new_species;
+            record.mons = mons;
+        }
+
+        self.encounters = encounters;
+        self.mon_stats = mon_stats;
+        self.current_chain = chain;
+        if let Some(last) = self.history.iter().rev().find(|r| !r.mons.is_empty()) {
+            self.last_encounter = last.mons.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encounter::DetectedWord;
+
+    /// Spells "pidgy" right and no longer counts "ditto".
+    struct Fixed;
+
+    impl MonFilter for Fixed {
+        fn classify(&self, _line: &str) -> Vec<DetectedWord> {
+            vec![]
+        }
+
+        fn recheck(&self, text: &str) -> Option<String> {
+            match text {
+                "pidgy" => Some("Pidgey".to_string()),
+                "ditto" => None,
+                _ => Some(text.to_string()),
+            }
+        }
+    }
+
+    fn battle(state: &mut EncounterState, mon: &str, raw: &str) {
+        state.count_battle(vec![mon.to_string()], vec![raw.to_string()], 1, 1);
+    }
+
+    #[test]
+    fn names_are_read_again_from_the_kept_text() {
+        let mut state = EncounterState::default();
+        battle(&mut state, "Abra", "abra");
+        battle(&mut state, "Abra", "abra");
+        battle(&mut state, "Pidgy", "pidgy");
+        battle(&mut state, "Ditto", "ditto");
+        battle(&mut state, "Pidgy", "pidgy");
+        // The first two encounters are from before the history was kept.
+        state.history.drain(..2);
+        state.recompute_stats(&Fixed);
+
+        assert_eq!(state.encounters, 5);
+        assert_eq!(state.mon_stats["Abra"].count, 2);
+        let pidgey = MonStat {
+            count: 2,
+            first_seen: 3,
+            last_seen: 5,
+            longest_chain: 1,
+        };
+        assert_eq!(state.mon_stats["Pidgey"], pidgey);
+        assert!(!state.mon_stats.contains_key("Pidgy") && !state.mon_stats.contains_key("Ditto"));
+
+        let encounters = state
+            .history
+            .iter()
+            .map(|r| r.encounter)
+            .collect::<Vec<_>>();
+        assert_eq!(encounters, [3, 4, 5]);
+        assert!(state.history[1].mons.is_empty());
+        assert_eq!(state.history[0].new_species, ["Pidgey"]);
+        assert_eq!(state.last_encounter, ["Pidgey"]);
+    }
+
+    #[test]
+    fn nothing_to_recompute_without_history() {
+        let mut state = EncounterState::default();
+        battle(&mut state, "Ditto", "ditto");
+        state.history.clear();
+        state.recompute_stats(&Fixed);
+        assert_eq!(state.mon_stats["Ditto"].count, 1);
+    }
+}
@@ -0,0 +1,12 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Returns a flag that is set once the process is asked to stop with Ctrl-C or SIGTERM,
+/// instead of being killed mid-cycle. Detection loops check it between cycles so the state
+/// is saved and capture is released before exiting. Can only be installed once per process.
+pub fn stop_on_signal() -> Result<Arc<AtomicBool>, ctrlc::Error> {
+    let should_stop = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&should_stop);
+    ctrlc::set_handler(move || flag.store(true, Ordering::Relaxed))?;
+    Ok(should_stop)
+}
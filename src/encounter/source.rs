@@ -0,0 +1,176 @@
+use image::DynamicImage;
+use std::path::PathBuf;
+use std::time::Duration;
+use xcap::{Monitor, Window};
+
+use super::{game_exist, BackgroundSource, CaptureConfig, EncounterError, TimedSource, APP_NAME};
+
+/// Where frames come from. Cropping and preprocessing happen after `grab`.
+pub trait ScreenSource {
+    fn grab(&mut self) -> Result<DynamicImage, EncounterError>;
+}
+
+/// Every open window. Fails with `EncounterError::NoDisplay` when there is no display server,
+/// so only call it when a frame has to be captured from the screen.
+pub fn game_windows() -> Result<Vec<Window>, EncounterError> {
+    Window::all().map_err(EncounterError::no_display)
+}
+
+/// Captures `window`, returns `None` when it is gone.
+fn capture_window(window: &Window) -> Option<DynamicImage> {
+    window.capture_image().ok().map(DynamicImage::ImageRgba8)
+}
+
+/// Captures the game window. The window found is reused until capturing it fails, then it is
+/// looked up again because the game can recreate it. `fallback` is used when it can't be found.
+pub struct GameWindowSource<'a> {
+    fallback: &'a Window,
+    window: Option<Window>,
+}
+
+impl<'a> GameWindowSource<'a> {
+    pub fn new(fallback: &'a Window) -> Self {
+        Self {
+            fallback,
+            window: None,
+        }
+    }
+}
+
+impl ScreenSource for GameWindowSource<'_> {
+    fn grab(&mut self) -> Result<DynamicImage, EncounterError> {
+        if let Some(img) = self.window.as_ref().and_then(capture_window) {
+            return Ok(img);
+        }
+
+        let windows = game_windows()?;
+        self.window = windows.into_iter().find(|w| game_exist(&w));
+        let window = self.window.as_ref().unwrap_or(self.fallback);
+        let img = window.capture_image().map_err(EncounterError::capture)?;
+        Ok(DynamicImage::ImageRgba8(img))
+    }
+}
+
+/// Captures the first window whose title contains `title`, ignoring case, for emulators and other games.
+/// Like `GameWindowSource` the window is only looked up again once capturing it fails.
+pub struct WindowSource {
+    title: String,
+    window: Option<Window>,
+}
+
+impl WindowSource {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_lowercase(),
+            window: None,
+        }
+    }
+}
+
+impl ScreenSource for WindowSource {
+    fn grab(&mut self) -> Result<DynamicImage, EncounterError> {
+        if let Some(img) = self.window.as_ref().and_then(capture_window) {
+            return Ok(img);
+        }
+
+        let windows = game_windows()?;
+        let window = windows
+            .into_iter()
+            .find(|w| w.title().to_lowercase().contains(&self.title))
+            .ok_or_else(|| EncounterError::WindowNotFound(self.title.clone()))?;
+        let img = window.capture_image().map_err(EncounterError::capture)?;
+        self.window = Some(window);
+        Ok(DynamicImage::ImageRgba8(img))
+    }
+}
+
+/// Captures a whole display, see `list_displays`.
+pub struct DisplaySource {
+    index: usize,
+}
+
+impl DisplaySource {
+    pub fn new(index: usize) -> Self {
+        Self { index }
+    }
+}
+
+impl ScreenSource for DisplaySource {
+    fn grab(&mut self) -> Result<DynamicImage, EncounterError> {
+        let img = get_display(self.index)?
+            .capture_image()
+            .map_err(EncounterError::capture)?;
+        Ok(DynamicImage::ImageRgba8(img))
+    }
+}
+
+/// Reads the same image file on every grab, for testing detection on a saved screenshot.
+pub struct FileSource {
+    path: PathBuf,
+}
+
+impl FileSource {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl ScreenSource for FileSource {
+    fn grab(&mut self) -> Result<DynamicImage, EncounterError> {
+        Ok(image::open(&self.path)?)
+    }
+}
+
+/// The live source selected by `capture`: a display or window when one is configured, otherwise
+/// the game `window`. Fails when the game window is needed but wasn't found.
+/// With `background_interval_ms` set the source runs on a background thread.
+pub fn screen_source<'a>(
+    capture: &CaptureConfig,
+    window: Option<&'a Window>,
+) -> Result<Box<dyn ScreenSource + 'a>, EncounterError> {
+    if let Some(interval_ms) = capture.background_interval_ms {
+        let interval = Duration::from_millis(interval_ms);
+        return Ok(Box::new(BackgroundSource::start(capture, interval)));
+    }
+
+    match (capture.display, &capture.window_title, window) {
+        (Some(index), _, _) => Ok(Box::new(DisplaySource::new(index))),
+        (None, Some(title), _) => Ok(Box::new(WindowSource::new(title))),
+        (None, None, Some(window)) => Ok(Box::new(GameWindowSource::new(window))),
+        (None, None, None) => Err(EncounterError::WindowNotFound(APP_NAME.to_string())),
+    }
+}
+
+/// The source for live detection, `screen_source` captured by a `TimedSource` when a
+/// `frame_timeout` is set. The game window is looked up here either way, so a missing game
+/// fails right away.
+pub fn live_source<'a>(
+    capture: &CaptureConfig,
+    window: Option<&'a Window>,
+    frame_timeout: Option<Duration>,
+) -> Result<Box<dyn ScreenSource + 'a>, EncounterError> {
+    let source = screen_source(capture, window)?;
+    match frame_timeout {
+        Some(timeout) => Ok(Box::new(TimedSource::new(capture, timeout))),
+        None => Ok(source),
+    }
+}
+
+pub fn list_displays() -> Vec<(usize, u32, u32)> {
+    Monitor::all()
+        .unwrap_or_default()
+        .iter()
+        .enumerate()
+        .map(|(index, monitor)| (index, monitor.width(), monitor.height()))
+        .collect()
+}
+
+fn get_display(index: usize) -> Result<Monitor, EncounterError> {
+    let monitors = Monitor::all().map_err(EncounterError::no_display)?;
+    let available = monitors.len();
+
+    monitors
+        .into_iter()
+        .nth(index)
+        .ok_or(EncounterError::DisplayNotFound { index, available })
+}
@@ -0,0 +1,273 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+use super::{
+    migrate, EncounterError, EncounterRecord, EncounterState, MonStat, StateStore, STATE_VERSION,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS encounters (
+        battle INTEGER PRIMARY KEY,
+        encounter INTEGER NOT NULL,
+        timestamp INTEGER NOT NULL,
+        mons TEXT NOT NULL,
+        new_species TEXT NOT NULL,
+        frames INTEGER NOT NULL DEFAULT 0,
+        brief INTEGER NOT NULL DEFAULT 0,
+        method TEXT,
+        resets INTEGER NOT NULL DEFAULT 0,
+        raw TEXT NOT NULL DEFAULT '[]'
+    );
+    CREATE TABLE IF NOT EXISTS species (
+        name TEXT PRIMARY KEY,
+        count INTEGER NOT NULL,
+        first_seen INTEGER NOT NULL,
+        last_seen INTEGER NOT NULL,
+        longest_chain INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS state (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        json TEXT NOT NULL
+    );
+";
+
+/// Whether `path` should hold a SQLite database instead of a JSON state file.
+pub fn is_database(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ["db", "sqlite"].contains(&ext.to_lowercase().as_str()))
+}
+
+/// Columns added to `encounters` after its first release, with their definition.
+const ADDED_COLUMNS: [(&str, &str); 5] = [
+    ("frames", "INTEGER NOT NULL DEFAULT 0"),
+    ("brief", "INTEGER NOT NULL DEFAULT 0"),
+    ("method", "TEXT"),
+    ("resets", "INTEGER NOT NULL DEFAULT 0"),
+    ("raw", "TEXT NOT NULL DEFAULT '[]'"),
+];
+
+fn open(path: &Path) -> Result<Connection, EncounterError> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+
+    let columns = conn
+        .prepare("SELECT name FROM pragma_table_info('encounters')")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for (name, definition) in ADDED_COLUMNS {
+        if !columns.iter().any(|column| column == name) {
+            conn.execute_batch(&format!(
+                "ALTER TABLE encounters ADD COLUMN {} {}",
+                name, definition
+            ))?;
+        }
+    }
+    Ok(conn)
+}
+
+/// State in a SQLite database. The `encounters` and `species` tables hold `history` and
+/// `mon_stats`, everything else is stored as JSON in `state`.
+pub struct SqliteStore {
+    path: PathBuf,
+}
+
+impl SqliteStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl StateStore for SqliteStore {
+    fn load(&self) -> Result<EncounterState, EncounterError> {
+        load_state(&self.path)
+    }
+
+    fn save(&self, state: &EncounterState) -> Result<(), EncounterError> {
+        save_state(state, &self.path, false)
+    }
+}
+
+/// Saves `state` in place of everything stored in the database at `path`.
+pub fn replace_state(state: &EncounterState, path: &Path) -> Result<(), EncounterError> {
+    save_state(state, path, true)
+}
+
+fn load_state(path: &Path) -> Result<EncounterState, EncounterError> {
+    let conn = open(path)?;
+    let json = conn
+        .query_row("SELECT json FROM state WHERE id = 0", [], |row| {
+            row.get::<_, String>(0)
+        })
+        .optional()?
+        .ok_or_else(|| EncounterError::Io(std::io::ErrorKind::NotFound.into()))?;
+    let value = serde_json::from_str::<Value>(&json)?;
+    let version = migrate::stored_version(&value);
+    let mut state = migrate::migrate(value)?;
+
+    let mut species =
+        conn.prepare("SELECT name, count, first_seen, last_seen, longest_chain FROM species")?;
+    for row in species.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            MonStat {
+                count: row.get(1)?,
+                first_seen: row.get(2)?,
+                last_seen: row.get(3)?,
+                longest_chain: row.get(4)?,
+            },
+        ))
+    })? {
+        let (name, stat) = row?;
+        state.mon_stats.insert(name, stat);
+    }
+
+    let mut encounters = conn.prepare(
+        "SELECT battle, encounter, timestamp, mons, new_species, frames, brief, method, resets, raw FROM encounters ORDER BY battle",
+    )?;
+    let rows = encounters.query_map([], |row| {
+        Ok((
+            row.get::<_, u32>(0)?,
+            row.get::<_, u32>(1)?,
+            row.get::<_, u64>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, u32>(5)?,
+            row.get::<_, bool>(6)?,
+            row.get::<_, Option<String>>(7)?,
+            row.get::<_, u32>(8)?,
+            row.get::<_, String>(9)?,
+        ))
+    })?;
+    for row in rows {
+        let (battle, encounter, timestamp, mons, new_species, frames, brief, method, resets, raw) =
+            row?;
+        state.history.push(EncounterRecord {
+            mons: serde_json::from_str(&mons)?,
+            timestamp,
+            encounter,
+            battle,
+            new_species: serde_json::from_str(&new_species)?,
+            frames,
+            brief,
+            method,
+            resets,
+            raw: serde_json::from_str(&raw)?,
+        });
+    }
+
+    // The tables still hold lowercased names, rewrite them or the next save adds every
+    // species again next to its old row.
+    if version < migrate::DISPLAY_NAMES_VERSION {
+        migrate::display_table_names(&mut state);
+        save_state(&state, path, true)?;
+    }
+
+    Ok(state)
+}
+
+/// Saves in one transaction. Only encounters newer than the last stored battle are inserted,
+/// so saving stays cheap however long the hunt gets. The last stored battle is written again
+/// as its frame count changes until the battle ends. With `rewrite` every stored encounter
+/// and species is replaced, for a state whose older encounters were changed.
+fn save_state(state: &EncounterState, path: &Path, rewrite: bool) -> Result<(), EncounterError> {
+    let mut conn = open(path)?;
+    let tx = conn.transaction()?;
+    if rewrite {
+        tx.execute_batch("DELETE FROM encounters; DELETE FROM species;")?;
+    }
+
+    let stored: u32 = tx.query_row(
+        "SELECT COALESCE(MAX(battle), 0) FROM encounters",
+        [],
+        |row| row.get(0),
+    )?;
+    for record in state.history.iter().filter(|r| r.battle >= stored) {
+        tx.execute(
+            "INSERT OR REPLACE INTO encounters VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                record.battle,
+                record.encounter,
+                record.timestamp,
+                serde_json::to_string(&record.mons)?,
+                serde_json::to_string(&record.new_species)?,
+                record.frames,
+                record.brief,
+                record.method,
+                record.resets,
+                serde_json::to_string(&record.raw)?,
+            ],
+        )?;
+    }
+
+    for (name, stat) in &state.mon_stats {
+        tx.execute(
+            "INSERT OR REPLACE INTO species VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                name,
+                stat.count,
+                stat.first_seen,
+                stat.last_seen,
+                stat.longest_chain
+            ],
+        )?;
+    }
+
+    // The tables hold these, the JSON only the rest of the state.
+    let mut json = serde_json::to_value(state)?;
+    json["history"] = json!([]);
+    json["mon_stats"] = json!({});
+    json["version"] = STATE_VERSION.into();
+    tx.execute(
+        "INSERT OR REPLACE INTO state VALUES (0, ?1)",
+        params![json.to_string()],
+    )?;
+
+    tx.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lowercase_tables_of_version_2_are_rewritten() {
+        let path = std::env::temp_dir().join("rencounter-sqlite-v2.db");
+        let _ = std::fs::remove_file(&path);
+        let conn = open(&path).unwrap();
+        let mut json = serde_json::to_value(EncounterState::default()).unwrap();
+        json["version"] = 2.into();
+        conn.execute(
+            "INSERT INTO state VALUES (0, ?1)",
+            params![json.to_string()],
+        )
+        .unwrap();
+        conn.execute_batch(
+            "INSERT INTO species VALUES ('mr. mime', 2, 1, 2, 1);
+             INSERT INTO encounters (battle, encounter, timestamp, mons, new_species)
+                 VALUES (1, 1, 0, '[\"mr. mime\"]', '[\"mr. mime\"]'),
+                        (2, 2, 0, '[\"mr. mime\"]', '[]');",
+        )
+        .unwrap();
+        drop(conn);
+
+        let store = SqliteStore::new(&path);
+        let state = store.load().unwrap();
+        assert_eq!(state.mon_stats["Mr. Mime"].count, 2);
+        assert_eq!(state.history[0].new_species, ["Mr. Mime"]);
+        assert_eq!(state.history[1].mons, ["Mr. Mime"]);
+        store.save(&state).unwrap();
+
+        let conn = open(&path).unwrap();
+        let names = conn
+            .prepare("SELECT name FROM species")
+            .unwrap()
+            .query_map([], |row| row.get::<_, String>(0))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(names, ["Mr. Mime"]);
+    }
+}
@@ -0,0 +1,164 @@
+use std::time::{Duration, Instant};
+
+use super::{EncounterState, Mode};
+
+pub const DEFAULT_SHINY_ODDS: u32 = 30000;
+
+#[cfg(feature = "tui")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OddsReport {
+    pub encounters: u32,
+    /// Chance of having met at least one shiny by now, between 0 and 1.
+    pub shiny_probability: f64,
+    /// Times the target mon was encountered, 0 without a target.
+    pub target_encounters: u32,
+}
+
+impl EncounterState {
+    #[cfg(feature = "tui")]
+    pub fn odds_report(&self) -> OddsReport {
+        let odds = self.shiny_odds.max(1) as f64;
+        let target_encounters = self
+            .target_mon
+            .as_ref()
+            .and_then(|target| self.mon_stat(target))
+            .map_or(0, |stat| stat.count);
+
+        OddsReport {
+            encounters: self.encounters,
+            shiny_probability: 1.0 - (1.0 - 1.0 / odds).powf(self.encounters as f64),
+            target_encounters,
+        }
+    }
+
+    /// Encounters from now on after which at least one shiny was met with chance `p`.
+    /// Every encounter is an independent trial, so the answer doesn't depend on how many
+    /// encounters are already done: a long hunt is never "due" a shiny.
+    /// `u64::MAX` when `p` is 1 or more.
+    #[cfg(feature = "tui")]
+    pub fn expected_encounters_for_probability(&self, p: f64) -> u64 {
+        if p <= 0.0 {
+            return 0;
+        }
+        if p >= 1.0 || self.shiny_odds <= 1 {
+            return if p >= 1.0 { u64::MAX } else { 1 };
+        }
+
+        let miss = 1.0 - 1.0 / self.shiny_odds as f64;
+        ((1.0 - p).ln() / miss.ln()).ceil() as u64
+    }
+
+    /// How often each gap between two battles with `species`, ignoring case, occurs in
+    /// `history`: index `n` counts the sightings that came `n` encounters after the previous
+    /// one. Empty when it was met fewer than two times.
+    pub fn gap_histogram(&self, species: &str) -> Vec<u32> {
+        let species = species.to_lowercase();
+        let sightings = self
+            .history
+            .iter()
+            .filter(|record| record.mons.iter().any(|mon| mon.to_lowercase() == species))
+            .map(|record| record.encounter)
+            .collect::<Vec<_>>();
+
+        let mut histogram = vec![];
+        for pair in sightings.windows(2) {
+            let gap = pair[1].saturating_sub(pair[0]) as usize;
+            if histogram.len() <= gap {
+                histogram.resize(gap + 1, 0);
+            }
+            histogram[gap] += 1;
+        }
+        histogram
+    }
+
+    /// Soft resets since the last counted encounter, all of them before the first.
+    #[cfg(feature = "tui")]
+    pub fn resets_since_encounter(&self) -> u32 {
+        let last = self.history.last().map_or(0, |record| record.resets);
+        self.resets.saturating_sub(last)
+    }
+
+    /// Time spent hunting over every session, see `track_active_time`.
+    pub fn active_duration(&self) -> Duration {
+        let running = self
+            .active_since
+            .filter(|_| !matches!(self.mode, Mode::Init | Mode::Pause))
+            .map_or(Duration::ZERO, |since| since.elapsed());
+        Duration::from_millis(self.active_ms) + running
+    }
+
+    /// Adds the time since the last call to `active_ms` when hunting now, call once per
+    /// detection cycle. Time before a restart or in Init or Pause is not counted.
+    pub(super) fn track_active_time(&mut self) {
+        let hunting = !matches!(self.mode, Mode::Init | Mode::Pause);
+        let now = Instant::now();
+        if let Some(since) = self.active_since.filter(|_| hunting) {
+            self.active_ms += now.duration_since(since).as_millis() as u64;
+        }
+        self.active_since = hunting.then_some(now);
+    }
+
+    /// Encounters counted after the last battle with `species`, ignoring case, taken from
+    /// `history`. `None` when it was never logged there.
+    pub fn encounters_since(&self, species: &str) -> Option<u32> {
+        let species = species.to_lowercase();
+        self.history
+            .iter()
+            .rev()
+            .find(|record| record.mons.iter().any(|mon| mon.to_lowercase() == species))
+            .map(|record| self.encounters.saturating_sub(record.encounter))
+    }
+}
+
+/// Encounter rate of the running session, time spent in Init or Pause is not counted.
+#[cfg(feature = "tui")]
+#[derive(Debug, Clone)]
+pub struct SessionStats {
+    started: Instant,
+    start_encounters: u32,
+    encounters: u32,
+    paused: Duration,
+    paused_since: Option<Instant>,
+}
+
+#[cfg(feature = "tui")]
+impl SessionStats {
+    pub fn new(state: &EncounterState) -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            start_encounters: state.encounters,
+            encounters: state.encounters,
+            paused: Duration::ZERO,
+            paused_since: Some(now),
+        }
+    }
+
+    /// Call once per detection cycle.
+    pub fn update(&mut self, state: &EncounterState) {
+        self.encounters = state.encounters;
+
+        let is_paused = matches!(state.mode, Mode::Init | Mode::Pause);
+        match (is_paused, self.paused_since) {
+            (true, None) => self.paused_since = Some(Instant::now()),
+            (false, Some(since)) => {
+                self.paused += since.elapsed();
+                self.paused_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn active_duration(&self) -> Duration {
+        let paused = self.paused + self.paused_since.map_or(Duration::ZERO, |s| s.elapsed());
+        self.started.elapsed().saturating_sub(paused)
+    }
+
+    pub fn encounters_per_hour(&self) -> f64 {
+        let hours = self.active_duration().as_secs_f64() / 3600.0;
+        if hours == 0.0 {
+            return 0.0;
+        }
+        self.encounters.saturating_sub(self.start_encounters) as f64 / hours
+    }
+}
@@ -0,0 +1,188 @@
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use super::{migrate, EncounterError, EncounterState, STATE_VERSION};
+
+/// Where the state is persisted between runs.
+pub trait StateStore {
+    fn load(&self) -> Result<EncounterState, EncounterError>;
+    fn save(&self, state: &EncounterState) -> Result<(), EncounterError>;
+
+    /// Like `load`, a fresh state when nothing was saved yet. Any other error is returned, so
+    /// an unreadable state or one of a newer version is never saved over.
+    fn load_or_default(&self) -> Result<EncounterState, EncounterError> {
+        match self.load() {
+            Err(err) if err.is_not_found() => Ok(EncounterState::default()),
+            loaded => loaded,
+        }
+    }
+}
+
+/// The store for `path`: a SQLite database for `.db` and `.sqlite` paths when built with the
+/// `sqlite` feature, otherwise a JSON file.
+pub fn open_store(path: &Path) -> Box<dyn StateStore> {
+    #[cfg(feature = "sqlite")]
+    if super::sqlite::is_database(path) {
+        return Box::new(super::sqlite::SqliteStore::new(path));
+    }
+
+    Box::new(FileStore::new(path))
+}
+
+/// State as a JSON file, with a `.bak` copy of the previous save next to it.
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn read_state(path: &Path) -> Result<EncounterState, EncounterError> {
+    let state_json = fs::read_to_string(path)?;
+    migrate::migrate(serde_json::from_str(&state_json)?)
+}
+
+impl StateStore for FileStore {
+    /// Falls back to the backup of the previous save when the state file is missing or corrupt.
+    /// A state of a newer version is returned as an error, its backup is likely older. When the
+    /// backup can't be read either, the error of the state file is returned unless it is missing.
+    fn load(&self) -> Result<EncounterState, EncounterError> {
+        match read_state(&self.path) {
+            Err(err @ EncounterError::UnsupportedVersion(_)) => Err(err),
+            Err(err) => read_state(&suffixed_path(&self.path, ".bak")).map_err(|backup_err| {
+                if err.is_not_found() {
+                    backup_err
+                } else {
+                    err
+                }
+            }),
+            loaded => loaded,
+        }
+    }
+
+    /// Writes to a temporary file first and renames it over the state file, so a crash
+    /// mid-write never leaves a truncated state behind.
+    fn save(&self, state: &EncounterState) -> Result<(), EncounterError> {
+        let tmp_path = suffixed_path(&self.path, ".tmp");
+        let mut state_json = serde_json::to_value(state)?;
+        state_json["version"] = STATE_VERSION.into();
+        let state_json = serde_json::to_string(&state_json)?;
+        fs::write(&tmp_path, state_json)?;
+
+        if self.path.exists() {
+            fs::rename(&self.path, suffixed_path(&self.path, ".bak"))?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Only writes the state when it changed since the last write, and then at most once per
+/// `interval`. A change held back by the interval is written by the first save after it,
+/// save through the inner store on exit so it isn't lost.
+pub struct ThrottledStore {
+    inner: Box<dyn StateStore>,
+    interval: Duration,
+    /// The state as of the last write, to tell whether a save has anything new.
+    written: RefCell<Option<String>>,
+    last_write: Cell<Option<Instant>>,
+}
+
+impl ThrottledStore {
+    pub fn new(inner: Box<dyn StateStore>, interval: Duration) -> Self {
+        Self {
+            inner,
+            interval,
+            written: RefCell::new(None),
+            last_write: Cell::new(None),
+        }
+    }
+}
+
+impl StateStore for ThrottledStore {
+    fn load(&self) -> Result<EncounterState, EncounterError> {
+        self.inner.load()
+    }
+
+    fn save(&self, state: &EncounterState) -> Result<(), EncounterError> {
+        let json = serde_json::to_string(state)?;
+        let dirty = self.written.borrow().as_ref() != Some(&json);
+        let throttled = self
+            .last_write
+            .get()
+            .is_some_and(|last| last.elapsed() < self.interval);
+        if !dirty || throttled {
+            return Ok(());
+        }
+
+        self.inner.save(state)?;
+        self.written.replace(Some(json));
+        self.last_write.set(Some(Instant::now()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_in(name: &str) -> (FileStore, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("rencounter-store-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state.json");
+        (FileStore::new(&path), path)
+    }
+
+    #[test]
+    fn missing_state_starts_fresh() {
+        let (store, _) = store_in("missing");
+        let state = store.load_or_default().unwrap();
+        assert_eq!(state.encounters, 0);
+        assert!(state.history.is_empty());
+    }
+
+    #[test]
+    fn newer_state_is_not_replaced() {
+        let (store, path) = store_in("newer");
+        fs::write(&path, format!("{{\"version\": {}}}", STATE_VERSION + 1)).unwrap();
+        assert!(matches!(
+            store.load_or_default(),
+            Err(EncounterError::UnsupportedVersion(_))
+        ));
+    }
+
+    #[test]
+    fn corrupt_state_without_backup_is_an_error() {
+        let (store, path) = store_in("corrupt");
+        fs::write(&path, "{").unwrap();
+        assert!(matches!(
+            store.load_or_default(),
+            Err(EncounterError::Serde(_))
+        ));
+    }
+
+    #[test]
+    fn corrupt_state_falls_back_to_backup() {
+        let (store, path) = store_in("backup");
+        let saved = EncounterState {
+            encounters: 7,
+            ..Default::default()
+        };
+        store.save(&saved).unwrap();
+        store.save(&saved).unwrap();
+        fs::write(&path, "{").unwrap();
+        assert_eq!(store.load_or_default().unwrap().encounters, 7);
+    }
+}
@@ -0,0 +1,90 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+
+use super::{
+    encounter_process, DetectionConfig, EncounterError, EncounterRecord, EncounterState, Mode,
+    MonFilter, MonRecognizer, ScreenSource, StateStore,
+};
+
+/// Iterator over the encounters committed by `encounter_process`, see `encounter_stream`.
+pub struct EncounterStream<'a> {
+    recognizer: &'a dyn MonRecognizer,
+    state: &'a mut EncounterState,
+    source: &'a mut dyn ScreenSource,
+    config: &'a DetectionConfig,
+    filter: &'a dyn MonFilter,
+    store: &'a dyn StateStore,
+    control: &'a Receiver<Mode>,
+    should_stop: Option<Arc<AtomicBool>>,
+}
+
+/// Runs detection until the next encounter is committed and yields it, saving the state
+/// like `encounter_process` does. Errors are yielded as they happen, the stream ends once
+/// counting is stopped or paused.
+pub fn encounter_stream<'a>(
+    recognizer: &'a dyn MonRecognizer,
+    state: &'a mut EncounterState,
+    source: &'a mut dyn ScreenSource,
+    config: &'a DetectionConfig,
+    filter: &'a dyn MonFilter,
+    store: &'a dyn StateStore,
+    control: &'a Receiver<Mode>,
+) -> EncounterStream<'a> {
+    EncounterStream {
+        recognizer,
+        state,
+        source,
+        config,
+        filter,
+        store,
+        control,
+        should_stop: None,
+    }
+}
+
+impl EncounterStream<'_> {
+    /// Ends the stream once `should_stop` is set, see `stop_on_signal`. It is checked between
+    /// detection cycles, so the state saved by the last cycle is complete.
+    pub fn with_stop(mut self, should_stop: Arc<AtomicBool>) -> Self {
+        self.should_stop = Some(should_stop);
+        self
+    }
+
+    /// The state as of the last record, e.g. to publish it along with that record.
+    pub fn state(&self) -> &EncounterState {
+        self.state
+    }
+
+    fn stopped(&self) -> bool {
+        self.should_stop
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+}
+
+impl Iterator for EncounterStream<'_> {
+    type Item = Result<EncounterRecord, EncounterError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.stopped() || matches!(self.state.mode, Mode::Init | Mode::Pause) {
+                return None;
+            }
+
+            match encounter_process(
+                self.recognizer,
+                self.state,
+                self.source,
+                self.config,
+                self.filter,
+                self.store,
+                self.control,
+            ) {
+                Ok(Some(record)) => return Some(Ok(record)),
+                Ok(None) => continue,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::fmt;
+use std::time::Duration;
+
+use super::EncounterState;
+
+/// End of hunt report, see `EncounterState::summary`.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub encounters: u32,
+    pub species: usize,
+    /// Five most encountered species with their share of all encounters, in percent.
+    pub top_five: Vec<(String, u32, f64)>,
+    /// Species encountered only once, sorted by name.
+    pub seen_once: Vec<String>,
+    /// Active time of the session, when one was tracked.
+    pub session: Option<Duration>,
+    /// Time spent hunting over every session, see `EncounterState::active_duration`.
+    pub hunting: Duration,
+    /// Soft resets of a stationary hunt.
+    pub resets: u32,
+    /// Eggs hatched in an egg hunt.
+    pub eggs_hatched: u32,
+    /// The target mon and the encounters since it was last met, `None` when it never was.
+    pub dry_streak: Option<(String, Option<u32>)>,
+    /// Encounters of the target mon per method with their share of that method's encounters,
+    /// in percent, highest share first.
+    pub target_by_method: Vec<(String, u32, f64)>,
+}
+
+impl EncounterState {
+    /// `session` is the active time of the running session, see `SessionStats`.
+    pub fn summary(&self, session: Option<Duration>) -> Summary {
+        let mut stats = self.mon_stats.iter().collect::<Vec<_>>();
+        stats.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(b.0)));
+
+        let total = self.encounters.max(1) as f64;
+        let mut seen_once = stats
+            .iter()
+            .filter(|(_, stat)| stat.count == 1)
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<_>>();
+        seen_once.sort();
+
+        let mut target_by_method = self
+            .target_mon
+            .as_ref()
+            .map(|target| {
+                let target = target.to_lowercase();
+                self.method_stats
+                    .iter()
+                    .map(|(method, stats)| {
+                        let count = stats
+                            .mon_counts
+                            .iter()
+                            .find(|(name, _)| name.to_lowercase() == target)
+                            .map_or(0, |(_, count)| *count);
+                        let share = count as f64 / stats.encounters.max(1) as f64 * 100.0;
+                        (method.clone(), count, share)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        target_by_method.sort_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.0.cmp(&b.0)));
+
+        Summary {
+            encounters: self.encounters,
+            species: stats.len(),
+            top_five: stats
+                .iter()
+                .take(5)
+                .map(|(name, stat)| {
+                    let share = stat.count as f64 / total * 100.0;
+                    (name.to_string(), stat.count, share)
+                })
+                .collect(),
+            seen_once,
+            session,
+            hunting: self.active_duration(),
+            resets: self.resets,
+            eggs_hatched: self.eggs_hatched,
+            dry_streak: self
+                .target_mon
+                .as_ref()
+                .map(|target| (target.clone(), self.encounters_since(target))),
+            target_by_method,
+        }
+    }
+}
+
+/// `duration` as e.g. `3h 07m`.
+pub fn hours_minutes(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Encounters: {}", self.encounters)?;
+        writeln!(f, "Species: {}", self.species)?;
+        if let Some(session) = self.session {
+            writeln!(f, "Session: {}", hours_minutes(session))?;
+        }
+        writeln!(f, "Hunting time: {}", hours_minutes(self.hunting))?;
+        if self.resets > 0 {
+            writeln!(f, "Soft resets: {}", self.resets)?;
+        }
+        if self.eggs_hatched > 0 {
+            writeln!(f, "Eggs hatched: {}", self.eggs_hatched)?;
+        }
+
+        match &self.dry_streak {
+            Some((target, Some(since))) => writeln!(
+                f,
+                "Dry streak: {} encounters since the last {}",
+                since, target
+            )?,
+            Some((target, None)) => writeln!(f, "Dry streak: no {} met yet", target)?,
+            None => {}
+        }
+
+        let target = self.dry_streak.as_ref().map(|(target, _)| target);
+        if let Some(target) = target.filter(|_| !self.target_by_method.is_empty()) {
+            writeln!(f, "{} by method:", target)?;
+            for (method, count, share) in &self.target_by_method {
+                writeln!(f, "  {}: {} ({:.1}%)", method, count, share)?;
+            }
+        }
+
+        writeln!(f, "Top 5:")?;
+        for (name, count, share) in &self.top_five {
+            writeln!(f, "  {}: {} ({:.1}%)", name, count, share)?;
+        }
+        if !self.seen_once.is_empty() {
+            writeln!(f, "Seen once: {}", self.seen_once.join(", "))?;
+        }
+        Ok(())
+    }
+}
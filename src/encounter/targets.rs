@@ -0,0 +1,45 @@
+use std::collections::HashMap;
+
+use super::{EncounterRecord, EncounterState};
+
+impl EncounterState {
+    fn species_count(&self, species: &str) -> u32 {
+        self.mon_stat(species).map_or(0, |stat| stat.count)
+    }
+
+    /// Species of `record` whose count reached its target with this encounter, with the target.
+    /// Call it after `record` was counted, a target is only reported once.
+    pub fn completed_targets(
+        &self,
+        record: &EncounterRecord,
+        targets: &HashMap<String, u32>,
+    ) -> Vec<(String, u32)> {
+        let mut completed: Vec<(String, u32)> = vec![];
+        for (species, &target) in targets {
+            let in_record = record
+                .mons
+                .iter()
+                .filter(|mon| mon.eq_ignore_ascii_case(species))
+                .count() as u32;
+            let count = self.species_count(species);
+            if in_record > 0 && count >= target && count.saturating_sub(in_record) < target {
+                completed.push((species.clone(), target));
+            }
+        }
+        completed.sort();
+        completed
+    }
+
+    /// Encounters still needed per species with a target, sorted by name. Reached targets are 0.
+    pub fn remaining_targets(&self, targets: &HashMap<String, u32>) -> Vec<(String, u32)> {
+        let mut remaining = targets
+            .iter()
+            .map(|(species, &target)| {
+                let left = target.saturating_sub(self.species_count(species));
+                (species.clone(), left)
+            })
+            .collect::<Vec<_>>();
+        remaining.sort();
+        remaining
+    }
+}
@@ -0,0 +1,50 @@
+use image::{GrayImage, Luma};
+
+/// Level between the dark and light pixels of `img` by Otsu's method, at which the
+/// variance between both classes is largest.
+pub(super) fn otsu_level(img: &GrayImage) -> u8 {
+    let mut histogram = [0u64; 256];
+    for pixel in img.pixels() {
+        histogram[pixel[0] as usize] += 1;
+    }
+
+    let total = img.pixels().len() as f64;
+    let sum_all = histogram
+        .iter()
+        .enumerate()
+        .map(|(level, count)| level as f64 * *count as f64)
+        .sum::<f64>();
+
+    let mut best = (0u8, 0.0);
+    let mut dark_count = 0.0;
+    let mut dark_sum = 0.0;
+    for (level, count) in histogram.iter().enumerate() {
+        dark_count += *count as f64;
+        dark_sum += level as f64 * *count as f64;
+        let light_count = total - dark_count;
+        if dark_count == 0.0 || light_count == 0.0 {
+            continue;
+        }
+
+        let dark_mean = dark_sum / dark_count;
+        let light_mean = (sum_all - dark_sum) / light_count;
+        let variance = dark_count * light_count * (dark_mean - light_mean).powi(2);
+        if variance > best.1 {
+            best = (level as u8, variance);
+        }
+    }
+    best.0
+}
+
+/// Turns `img` into black text on a white background: pixels above `level` are one
+/// color, the rest the other, with the most common color becoming the background.
+pub(super) fn binarize(mut img: GrayImage, level: u8) -> GrayImage {
+    let light = img.pixels().filter(|p| p[0] > level).count();
+    let light_background = light * 2 >= img.pixels().len();
+
+    for pixel in img.pixels_mut() {
+        let white = (pixel[0] > level) == light_background;
+        *pixel = Luma([if white { 255 } else { 0 }]);
+    }
+    img
+}
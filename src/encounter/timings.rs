@@ -0,0 +1,62 @@
+use std::fmt;
+use std::ops::AddAssign;
+use std::time::Duration;
+
+/// Time spent in each stage of detection, see `DetectionConfig::log_timings`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StageTimings {
+    /// Grabbing the frame, cropping and preprocessing the regions.
+    pub capture: Duration,
+    /// Turning the regions into the input tensor of the OCR engine.
+    pub prepare: Duration,
+    /// Finding the words and grouping them into lines.
+    pub detect: Duration,
+    pub recognize: Duration,
+}
+
+impl StageTimings {
+    /// The mean time of each stage over `frames` frames, zero for none.
+    pub fn per_frame(&self, frames: u32) -> Self {
+        let mean = |total: Duration| total.checked_div(frames).unwrap_or_default();
+        Self {
+            capture: mean(self.capture),
+            prepare: mean(self.prepare),
+            detect: mean(self.detect),
+            recognize: mean(self.recognize),
+        }
+    }
+}
+
+impl AddAssign for StageTimings {
+    fn add_assign(&mut self, other: Self) {
+        self.capture += other.capture;
+        self.prepare += other.prepare;
+        self.detect += other.detect;
+        self.recognize += other.recognize;
+    }
+}
+
+impl fmt::Display for StageTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "capture {:?}, prepare {:?}, detect {:?}, recognize {:?}",
+            self.capture, self.prepare, self.detect, self.recognize
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn per_frame_of_no_frames_is_zero() {
+        let total = StageTimings {
+            detect: Duration::from_millis(30),
+            ..StageTimings::default()
+        };
+        assert_eq!(total.per_frame(0), StageTimings::default());
+        assert_eq!(total.per_frame(3).detect, Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,69 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use super::{
+    encounter_process, mode_filter, DetectionConfig, EncounterError, EncounterRecord,
+    EncounterState, Mode, MonRecognizer, ScreenSource, SpeciesDictionary, StateStore,
+};
+
+/// One hunt with its own config, capture source, state and store, e.g. one of two emulator
+/// instances. Several trackers can be stepped in turn from one thread, sharing a recognizer.
+pub struct Tracker<'a> {
+    pub config: DetectionConfig,
+    pub state: EncounterState,
+    dictionary: Option<SpeciesDictionary>,
+    source: Box<dyn ScreenSource + 'a>,
+    store: Box<dyn StateStore>,
+    mode_sender: Sender<Mode>,
+    mode_receiver: Receiver<Mode>,
+}
+
+impl<'a> Tracker<'a> {
+    /// Starts from the state in `store`, a fresh one when it has none yet, waiting in
+    /// `Mode::Init` for `request_mode`. Fails when the stored state can't be loaded, see
+    /// `StateStore::load_or_default`.
+    pub fn new(
+        config: DetectionConfig,
+        source: Box<dyn ScreenSource + 'a>,
+        store: Box<dyn StateStore>,
+    ) -> Result<Self, EncounterError> {
+        let mut state = store.load_or_default()?;
+        state.mode = Mode::Init;
+        let (mode_sender, mode_receiver) = mpsc::channel();
+        Ok(Self {
+            config,
+            state,
+            dictionary: None,
+            source,
+            store,
+            mode_sender,
+            mode_receiver,
+        })
+    }
+
+    pub fn with_dictionary(mut self, dictionary: Option<SpeciesDictionary>) -> Self {
+        self.dictionary = dictionary;
+        self
+    }
+
+    /// Switches to `mode` at the start of the next step, like a hotkey would.
+    pub fn request_mode(&self, mode: Mode) {
+        let _ = self.mode_sender.send(mode);
+    }
+
+    /// Runs one detection cycle, see `encounter_process`. Saves through the store as it does.
+    pub fn step(
+        &mut self,
+        recognizer: &dyn MonRecognizer,
+    ) -> Result<Option<EncounterRecord>, EncounterError> {
+        let filter = mode_filter(&self.config, self.dictionary.as_ref());
+        encounter_process(
+            recognizer,
+            &mut self.state,
+            self.source.as_mut(),
+            &self.config,
+            filter.as_ref(),
+            self.store.as_ref(),
+            &self.mode_receiver,
+        )
+    }
+}
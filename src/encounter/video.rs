@@ -0,0 +1,93 @@
+use image::{DynamicImage, RgbaImage};
+use std::io::{ErrorKind, Read};
+use std::path::Path;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::time::Duration;
+
+use super::{EncounterError, ScreenSource};
+
+/// Frames of a recorded video, decoded by the `ffmpeg` and `ffprobe` programs on the PATH.
+/// Every grab returns the next sampled frame, once the video ends grabs fail and
+/// `finished` turns true.
+pub struct VideoSource {
+    ffmpeg: Child,
+    frames: ChildStdout,
+    width: u32,
+    height: u32,
+    finished: bool,
+}
+
+impl VideoSource {
+    /// Starts decoding `path`, keeping one frame every `interval` of video time.
+    pub fn open(path: &Path, interval: Duration) -> Result<Self, EncounterError> {
+        let probe = Command::new("ffprobe")
+            .args(["-v", "error", "-select_streams", "v:0"])
+            .args(["-show_entries", "stream=width,height", "-of", "csv=p=0:s=x"])
+            .arg(path)
+            .output()
+            .map_err(|err| EncounterError::capture(format!("can't run ffprobe: {}", err)))?;
+        if !probe.status.success() {
+            return Err(EncounterError::capture(
+                String::from_utf8_lossy(&probe.stderr).trim(),
+            ));
+        }
+        let size = String::from_utf8_lossy(&probe.stdout);
+        let (width, height) = size
+            .trim()
+            .split_once('x')
+            .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+            .ok_or_else(|| EncounterError::capture(format!("unexpected video size {:?}", size)))?;
+
+        let fps = 1000.0 / interval.as_millis().max(1) as f64;
+        let mut ffmpeg = Command::new("ffmpeg")
+            .args(["-v", "error", "-i"])
+            .arg(path)
+            .args(["-vf", &format!("fps={}", fps)])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba", "-"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| EncounterError::capture(format!("can't run ffmpeg: {}", err)))?;
+        let frames = ffmpeg
+            .stdout
+            .take()
+            .ok_or_else(|| EncounterError::capture("ffmpeg has no output"))?;
+
+        Ok(Self {
+            ffmpeg,
+            frames,
+            width,
+            height,
+            finished: false,
+        })
+    }
+
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+}
+
+impl ScreenSource for VideoSource {
+    fn grab(&mut self) -> Result<DynamicImage, EncounterError> {
+        let mut frame = vec![0; self.width as usize * self.height as usize * 4];
+        match self.frames.read_exact(&mut frame) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                self.finished = true;
+                return Err(EncounterError::capture("end of video"));
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        RgbaImage::from_raw(self.width, self.height, frame)
+            .map(DynamicImage::ImageRgba8)
+            .ok_or_else(|| EncounterError::capture("video frame has the wrong size"))
+    }
+}
+
+impl Drop for VideoSource {
+    fn drop(&mut self) {
+        let _ = self.ffmpeg.kill();
+        let _ = self.ffmpeg.wait();
+    }
+}
@@ -0,0 +1,175 @@
+use std::cmp::Reverse;
+
+use super::dictionary::levenshtein;
+use super::FrameReading;
+
+/// One mon as read over the frames of a cycle.
+#[derive(Debug, Default)]
+struct Group {
+    /// Every spelling read for the mon and how often it was read.
+    spellings: Vec<(String, usize)>,
+    /// How many times the mon was read on each frame it appeared on.
+    frame_counts: Vec<usize>,
+}
+
+impl Group {
+    fn matches(&self, name: &str, merge_distance: usize) -> bool {
+        self.spellings
+            .iter()
+            .any(|(spelling, _)| levenshtein(spelling, name) <= merge_distance)
+    }
+
+    fn add_spelling(&mut self, name: &str) {
+        match self
+            .spellings
+            .iter_mut()
+            .find(|(spelling, _)| spelling == name)
+        {
+            Some((_, reads)) => *reads += 1,
+            None => self.spellings.push((name.to_string(), 1)),
+        }
+    }
+
+    /// Most read spelling, the first one read on a tie.
+    fn name(&self) -> &str {
+        self.spellings
+            .iter()
+            .enumerate()
+            .max_by_key(|(i, (_, reads))| (*reads, Reverse(*i)))
+            .map_or("", |(_, (spelling, _))| spelling)
+    }
+
+    /// Per-frame count most frames agree on, the larger one on a tie.
+    fn count(&self) -> usize {
+        let mut tally: Vec<(usize, usize)> = vec![];
+        for count in &self.frame_counts {
+            match tally.iter_mut().find(|(c, _)| c == count) {
+                Some((_, frames)) => *frames += 1,
+                None => tally.push((*count, 1)),
+            }
+        }
+        tally
+            .into_iter()
+            .max_by_key(|(count, frames)| (*frames, *count))
+            .map_or(0, |(count, _)| count)
+    }
+}
+
+/// Combines the mons read on every frame of a cycle into a single encounter.
+///
+/// Names within `merge_distance` edits of each other are the same mon. A mon is kept when it was
+/// read on at least `min_share` of the frames that found anything, e.g. 0.5 for half of them.
+/// Returns `None` when nothing is kept.
+pub fn merge_frames(
+    frames: &[&FrameReading],
+    merge_distance: usize,
+    min_share: f32,
+) -> Option<(Vec<String>, bool)> {
+    let detected = frames
+        .iter()
+        .filter(|frame| !frame.mons.is_empty())
+        .collect::<Vec<_>>();
+
+    let mut groups: Vec<Group> = vec![];
+    for frame in &detected {
+        let mut frame_counts = vec![0; groups.len()];
+        for mon in &frame.mons {
+            let index = match groups.iter().position(|g| g.matches(mon, merge_distance)) {
+                Some(index) => index,
+                None => {
+                    groups.push(Group::default());
+                    frame_counts.push(0);
+                    groups.len() - 1
+                }
+            };
+            groups[index].add_spelling(mon);
+            frame_counts[index] += 1;
+        }
+
+        for (group, count) in groups.iter_mut().zip(frame_counts) {
+            if count > 0 {
+                group.frame_counts.push(count);
+            }
+        }
+    }
+
+    let mons = groups
+        .iter()
+        .filter(|group| group.frame_counts.len() as f32 >= min_share * detected.len() as f32)
+        .flat_map(|group| std::iter::repeat(group.name().to_string()).take(group.count()))
+        .collect::<Vec<_>>();
+
+    if mons.is_empty() {
+        return None;
+    }
+    Some((mons, detected.iter().any(|frame| frame.lure_on)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(mons: &[&str]) -> FrameReading {
+        FrameReading {
+            mons: mons.iter().map(|mon| mon.to_string()).collect(),
+            ..FrameReading::default()
+        }
+    }
+
+    fn vote(frames: &[FrameReading], min_share: f32) -> Option<Vec<String>> {
+        let frames = frames.iter().collect::<Vec<_>>();
+        merge_frames(&frames, 1, min_share).map(|(mons, _)| mons)
+    }
+
+    #[test]
+    fn spelling_read_most_wins() {
+        let frames = [frame(&["Pidgay"]), frame(&["Pidgey"]), frame(&["Pidgey"])];
+        assert_eq!(vote(&frames, 0.5), Some(vec!["Pidgey".to_string()]));
+    }
+
+    #[test]
+    fn tied_spellings_keep_the_first_read() {
+        let frames = [frame(&["Pidgay"]), frame(&["Pidgey"])];
+        assert_eq!(vote(&frames, 0.5), Some(vec!["Pidgay".to_string()]));
+    }
+
+    #[test]
+    fn tied_counts_keep_the_larger_horde() {
+        let frames = [frame(&["Zubat", "Zubat"]), frame(&["Zubat"])];
+        assert_eq!(
+            vote(&frames, 0.5),
+            Some(vec!["Zubat".to_string(), "Zubat".to_string()])
+        );
+    }
+
+    #[test]
+    fn min_share_only_counts_frames_that_read_something() {
+        let frames = [
+            frame(&["Abra", "Zubat"]),
+            frame(&["Abra"]),
+            frame(&["Abra"]),
+            frame(&[]),
+            frame(&[]),
+        ];
+        assert_eq!(vote(&frames, 0.5), Some(vec!["Abra".to_string()]));
+        assert_eq!(vote(&frames, 0.3).map(|mons| mons.len()), Some(2));
+    }
+
+    #[test]
+    fn nothing_read_is_no_encounter() {
+        assert_eq!(vote(&[frame(&[]), frame(&[])], 0.5), None);
+        assert_eq!(vote(&[], 0.5), None);
+    }
+
+    #[test]
+    fn lure_on_any_frame_counts() {
+        let plain = frame(&["Abra"]);
+        let mut lured = frame(&["Abra"]);
+        lured.lure_on = true;
+        let frames = [&plain, &lured];
+        assert_eq!(
+            merge_frames(&frames, 1, 0.5).map(|(_, lure)| lure),
+            Some(true)
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Appends log records to a file, the terminal belongs to the counter UI.
+struct FileLogger {
+    file: Mutex<File>,
+    level: LevelFilter,
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis());
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(
+                file,
+                "{} {:<5} {}: {}",
+                timestamp,
+                record.level(),
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.flush();
+        }
+    }
+}
+
+/// Sends the records of `level` and above to `path` for the rest of the process.
+pub fn init(path: &Path, level: LevelFilter) -> Result<(), Box<dyn Error>> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    log::set_boxed_logger(Box::new(FileLogger {
+        file: Mutex::new(file),
+        level,
+    }))?;
+    log::set_max_level(level);
+    Ok(())
+}
@@ -4,120 +4,597 @@ extern crate rten_imageio;
 extern crate rten_tensor;
 
 mod encounter;
+mod logging;
+mod models;
+#[cfg(feature = "tui")]
+mod notify;
+#[cfg(feature = "http")]
+mod server;
+#[cfg(feature = "tui")]
 mod tui;
+#[cfg(feature = "websocket")]
+mod websocket;
 
-use core::panic;
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+#[cfg(feature = "tui")]
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use encounter::{
-    encounter_process, get_current_working_dir, load_state, save_state, EncounterState, Mode,
-    APP_NAME,
+    bench_dir, calibrate, debug_region_overlay, encounter_stream, game_windows,
+    get_current_working_dir, list_displays, live_source, load_config, load_state,
+    load_state_or_default, mode_filter, open_store, preview_detection, print_state, replace_state,
+    replay_dir, save_state, screen_source, stop_on_signal, DetectionConfig, DetectionMode,
+    EncounterState, FileSource, LanguageConfig, Mode, ReviewedFilter, ScreenSource,
+    SkipStaticFrames, SpeciesDictionary, ThrottledStore, TimedOcr, Tracker, APP_NAME,
+    DEFAULT_MAX_DISTANCE, DEFAULT_STATE_FILE,
 };
+#[cfg(feature = "tui")]
+use encounter::{
+    encounter_process, reset_state, EncounterRecord, HotkeyAction, Hotkeys, SessionStats,
+};
+use models::{build_engine, ModelPaths};
+#[cfg(feature = "tui")]
 use ratatui::{
-    layout::Alignment,
+    layout::{Alignment, Constraint, Flex, Layout},
     prelude::Stylize,
+    style::Style,
     symbols::border,
     text::Line,
     widgets::{
         block::{Position, Title},
-        Block, Borders, Paragraph,
+        Block, Borders, Paragraph, Row, Table,
     },
     Frame,
 };
 
-use std::fs;
-use std::{env, error::Error};
-use xcap::Window;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "tui")]
+use std::rc::Rc;
+#[cfg(feature = "tui")]
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+#[cfg(feature = "tui")]
+use std::sync::mpsc::{Receiver, Sender};
+#[cfg(any(feature = "tui", feature = "tokio"))]
+use std::sync::Arc;
 
-fn init_engine() -> Result<ocrs::OcrEngine, Box<dyn Error>> {
-    let (detection_path, recognition_path) = get_path_to_models();
-    let (detection_model, recognition_model) = load_rten_model(detection_path, recognition_path)?;
+/// Chance of a shiny the odds lines count the encounters for.
+#[cfg(feature = "tui")]
+const SHINY_GOAL: f64 = 0.9;
+/// Columns of the species table in the terminal UI, in characters.
+#[cfg(feature = "tui")]
+const TOP_SPECIES_WIDTH: u16 = 33;
+/// Encounters in the terminal feed and on `/recent`, see `--recent`.
+#[cfg(any(feature = "tui", feature = "http"))]
+const DEFAULT_RECENT_ENCOUNTERS: usize = 3;
+/// Times every frame of `--bench` is read.
+const DEFAULT_BENCH_ROUNDS: u32 = 3;
+#[cfg(feature = "video")]
+const DEFAULT_VIDEO_INTERVAL_MS: u64 = 500;
+#[cfg(feature = "tui")]
+const DEFAULT_ARCHIVE_DIR: &str = "archive";
 
-    create_engine(detection_model, recognition_model)
+#[derive(Debug, Clone)]
+struct Args {
+    debug: bool,
+    dry_run: bool,
+    #[cfg(feature = "tui")]
+    stream: bool,
+    confirm: bool,
+    list_displays: bool,
+    export_csv: Option<PathBuf>,
+    export_seen: bool,
+    export_gaps: Option<PathBuf>,
+    merge_path: Option<PathBuf>,
+    recompute: bool,
+    calibrate_path: Option<PathBuf>,
+    region_overlay_path: Option<PathBuf>,
+    summary: bool,
+    print_state: bool,
+    compact: bool,
+    image_path: Option<PathBuf>,
+    overlay_path: Option<PathBuf>,
+    replay_dir: Option<PathBuf>,
+    bench_dir: Option<PathBuf>,
+    bench_rounds: u32,
+    /// Config and state file of every hunt counted side by side with `--stream`.
+    instances: Vec<(PathBuf, PathBuf)>,
+    state_path: PathBuf,
+    #[cfg(feature = "tui")]
+    archive_dir: PathBuf,
+    target_mon: Option<String>,
+    route: Option<String>,
+    method: Option<String>,
+    #[cfg(feature = "tui")]
+    shiny_odds: Option<u32>,
+    #[cfg(any(feature = "tui", feature = "http"))]
+    recent_encounters: usize,
+    #[cfg(feature = "tui")]
+    webhook_url: Option<String>,
+    #[cfg(feature = "tui")]
+    notify_on: Vec<String>,
+    #[cfg(feature = "tui")]
+    notify_new_species: bool,
+    species_path: Option<PathBuf>,
+    max_distance: usize,
+    #[cfg(feature = "http")]
+    http_addr: Option<String>,
+    #[cfg(feature = "websocket")]
+    websocket_addr: Option<String>,
+    detection: DetectionConfig,
+    #[cfg(feature = "tui")]
+    targets: HashMap<String, u32>,
+    #[cfg(feature = "tui")]
+    hotkeys: Hotkeys,
+    languages: HashMap<String, LanguageConfig>,
+    language: Option<String>,
+    #[cfg(feature = "global-hotkeys")]
+    global_hotkeys: bool,
+    models: ModelPaths,
+    log_path: Option<PathBuf>,
+    log_level: log::LevelFilter,
+    #[cfg(feature = "video")]
+    video_interval_ms: u64,
+    #[cfg(feature = "tokio")]
+    tokio: bool,
 }
 
-fn create_engine(
-    detection_model: rten::Model,
-    recognition_model: rten::Model,
-) -> Result<ocrs::OcrEngine, Box<dyn Error>> {
-    let engine = ocrs::OcrEngine::new(ocrs::OcrEngineParams {
-        detection_model: Some(detection_model),
-        recognition_model: Some(recognition_model),
-        ..Default::default()
-    })?;
-    Ok(engine)
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            debug: false,
+            dry_run: false,
+            #[cfg(feature = "tui")]
+            stream: false,
+            confirm: false,
+            list_displays: false,
+            export_csv: None,
+            export_seen: false,
+            export_gaps: None,
+            merge_path: None,
+            recompute: false,
+            calibrate_path: None,
+            region_overlay_path: None,
+            summary: false,
+            print_state: false,
+            compact: false,
+            image_path: None,
+            overlay_path: None,
+            replay_dir: None,
+            bench_dir: None,
+            bench_rounds: DEFAULT_BENCH_ROUNDS,
+            instances: vec![],
+            state_path: PathBuf::from(DEFAULT_STATE_FILE),
+            #[cfg(feature = "tui")]
+            archive_dir: PathBuf::from(DEFAULT_ARCHIVE_DIR),
+            target_mon: None,
+            route: None,
+            method: None,
+            #[cfg(feature = "tui")]
+            shiny_odds: None,
+            #[cfg(any(feature = "tui", feature = "http"))]
+            recent_encounters: DEFAULT_RECENT_ENCOUNTERS,
+            #[cfg(feature = "tui")]
+            webhook_url: None,
+            #[cfg(feature = "tui")]
+            notify_on: vec![],
+            #[cfg(feature = "tui")]
+            notify_new_species: false,
+            species_path: None,
+            max_distance: DEFAULT_MAX_DISTANCE,
+            #[cfg(feature = "http")]
+            http_addr: None,
+            #[cfg(feature = "websocket")]
+            websocket_addr: None,
+            detection: DetectionConfig::default(),
+            #[cfg(feature = "tui")]
+            targets: HashMap::new(),
+            #[cfg(feature = "tui")]
+            hotkeys: Hotkeys::default(),
+            languages: HashMap::new(),
+            language: None,
+            #[cfg(feature = "global-hotkeys")]
+            global_hotkeys: false,
+            models: ModelPaths::default(),
+            log_path: None,
+            log_level: log::LevelFilter::Info,
+            #[cfg(feature = "video")]
+            video_interval_ms: DEFAULT_VIDEO_INTERVAL_MS,
+            #[cfg(feature = "tokio")]
+            tokio: false,
+        }
+    }
 }
 
-fn load_rten_model(
-    detection_path: String,
-    recognition_path: String,
-) -> Result<(rten::Model, rten::Model), Box<dyn Error>> {
-    let detection_model_data = fs::read(detection_path)?;
-    let rec_model_data = fs::read(recognition_path)?;
-    let detection_model = rten::Model::load(detection_model_data)?;
-    let recognition_model = rten::Model::load(rec_model_data)?;
-    Ok((detection_model, recognition_model))
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|w| w.trim().to_string())
+        .filter(|w| !w.is_empty())
+        .collect()
 }
 
-fn get_path_to_models() -> (String, String) {
-    let (exe_path, path) = get_current_working_dir();
+fn parse_args() -> Result<Args, lexopt::Error> {
+    use lexopt::prelude::*;
 
-    let detection_path = format!("{}/text-detection.rten", path);
-    let detection_path_exe = format!("{}/text-detection.rten", exe_path);
-    let recognition_path = format!("{}/text-recognition.rten", path);
-    let recognition_path_exe = format!("{}/text-recognition.rten", exe_path);
+    let mut args = Args::default();
+    let mut parser = lexopt::Parser::from_env();
+
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Value(value) if value == "debug" => args.debug = true,
+            Long("config") => {
+                let path = PathBuf::from(parser.value()?);
+                let config = load_config(&path)
+                    .map_err(|e| format!("can't load config {}: {}", path.display(), e))?;
+                args.detection = config.detection;
+                #[cfg(feature = "tui")]
+                {
+                    args.targets = config.targets;
+                    args.hotkeys = config.hotkeys;
+                }
+                args.languages = config.languages;
+                if config.language.is_some() {
+                    args.language = config.language;
+                }
+                #[cfg(any(feature = "tui", feature = "http"))]
+                if let Some(recent) = config.recent_encounters {
+                    args.recent_encounters = recent;
+                }
+            }
+            Long("language") => args.language = Some(parser.value()?.string()?),
+            Long("detection-model") => args.models.detection = Some(parser.value()?.into()),
+            Long("recognition-model") => args.models.recognition = Some(parser.value()?.into()),
+            Long("download-models") => args.models.download = true,
+            Long("threads") => args.models.threads = Some(parser.value()?.parse()?),
+            Long("event-log") => args.detection.event_log = Some(parser.value()?.into()),
+            Long("save-frames") => {
+                args.detection.save_encounter_frames = Some(parser.value()?.into())
+            }
+            Long("save-interval") => args.detection.save_interval_ms = parser.value()?.parse()?,
+            Long("brief-frames") => {
+                args.detection.brief_encounter_frames = parser.value()?.parse()?
+            }
+            Long("region") => args
+                .detection
+                .capture
+                .regions
+                .push(parser.value()?.parse()?),
+            Long("window") => args.detection.capture.window_title = Some(parser.value()?.string()?),
+            Long("display") => args.detection.capture.display = Some(parser.value()?.parse()?),
+            Long("background-capture") => {
+                args.detection.capture.background_interval_ms = Some(parser.value()?.parse()?)
+            }
+            Long("capture-retries") => args.detection.capture.retries = parser.value()?.parse()?,
+            Long("sleep-ms") => args.detection.sleep_ms = Some(parser.value()?.parse()?),
+            Long("max-fps") => args.detection.max_fps = Some(parser.value()?.parse()?),
+            Long("detect-frames") => args.detection.detect_frames = parser.value()?.parse()?,
+            Long("end-cycles") => args.detection.end_encounter_cycles = parser.value()?.parse()?,
+            Long("line-padding") => args.detection.line_padding = parser.value()?.parse()?,
+            Long("new-species-encounters") => {
+                args.detection.new_species_encounters = parser.value()?.parse()?
+            }
+            Long("mons-per-encounter") => {
+                args.detection.expected_mons_per_encounter = Some(parser.value()?.parse()?)
+            }
+            Long("cooldown-ms") => {
+                args.detection.encounter_cooldown_ms = parser.value()?.parse()?
+            }
+            Long("brightness") => args.detection.preprocess.brightness = parser.value()?.parse()?,
+            Long("scale") => args.detection.preprocess.scale = parser.value()?.parse()?,
+            Long("contrast") => args.detection.preprocess.contrast = parser.value()?.parse()?,
+            Long("no-grayscale") => args.detection.preprocess.grayscale = false,
+            Long("threshold") => args.detection.preprocess.threshold = parser.value()?.parse()?,
+            Long("static-threshold") => {
+                args.detection.static_frame_threshold = parser.value()?.parse()?
+            }
+            Long("timings") => args.detection.log_timings = true,
+            Long("frame-timeout") => {
+                args.detection.frame_timeout_ms = Some(parser.value()?.parse()?)
+            }
+            Long("vote-share") => args.detection.vote_share = parser.value()?.parse()?,
+            Long("merge-distance") => args.detection.merge_distance = parser.value()?.parse()?,
+            Long("level-markers") => {
+                args.detection.level_markers = split_list(&parser.value()?.string()?)
+            }
+            Long("normalize") => args.detection.normalize.push(parser.value()?.parse()?),
+            Long("name-pattern") => args.detection.name_patterns.push(parser.value()?.parse()?),
+            Long("banned-words") => {
+                args.detection.banned_words = split_list(&parser.value()?.string()?)
+            }
+            Long("ignore") => args.detection.ignore(&parser.value()?.string()?),
+            Long("alias") => args.detection.alias(&parser.value()?.string()?)?,
+            Long("keep-forms") => args.detection.merge_aliases = false,
+            Long("battle-tokens") => {
+                args.detection.battle_tokens = split_list(&parser.value()?.string()?)
+            }
+            Long("reset-tokens") => {
+                args.detection.reset_tokens = split_list(&parser.value()?.string()?)
+            }
+            Long("count-text") => {
+                args.detection.mode = DetectionMode::parse_generic(&parser.value()?.string()?)?
+            }
+            Long("hatch") => {
+                args.detection.mode = DetectionMode::parse_hatch(&parser.value()?.string()?)?
+            }
+            Long("pixel-order") => args.detection.capture.pixel_order = parser.value()?.parse()?,
+            Long("list-displays") => args.list_displays = true,
+            Long("dry-run") => args.dry_run = true,
+            #[cfg(feature = "tui")]
+            Long("stream") => args.stream = true,
+            // Without the terminal UI the counter always streams.
+            #[cfg(not(feature = "tui"))]
+            Long("stream") => {}
+            Long("confirm") => args.confirm = true,
+            Long("replay") => args.replay_dir = Some(parser.value()?.into()),
+            Long("bench") => args.bench_dir = Some(parser.value()?.into()),
+            Long("bench-rounds") => args.bench_rounds = parser.value()?.parse()?,
+            Long("instance") => {
+                let value = parser.value()?.string()?;
+                let (config, state) = value.split_once('=').ok_or_else(|| {
+                    format!("invalid instance '{}': expected config=state", value)
+                })?;
+                args.instances.push((config.into(), state.into()));
+            }
+            Long("image") => args.image_path = Some(parser.value()?.into()),
+            Long("overlay") => args.overlay_path = Some(parser.value()?.into()),
+            Long("export-csv") => args.export_csv = Some(parser.value()?.into()),
+            Long("export-seen") => args.export_seen = true,
+            Long("export-gaps") => args.export_gaps = Some(parser.value()?.into()),
+            Long("log") => args.log_path = Some(parser.value()?.into()),
+            Long("log-level") => args.log_level = parser.value()?.parse()?,
+            Long("calibrate") => args.calibrate_path = Some(parser.value()?.into()),
+            Long("region-overlay") => args.region_overlay_path = Some(parser.value()?.into()),
+            Long("merge") => args.merge_path = Some(parser.value()?.into()),
+            Long("recompute") => args.recompute = true,
+            Long("summary") => args.summary = true,
+            Long("print-state") => args.print_state = true,
+            Long("compact") => args.compact = true,
+            Long("state") => args.state_path = parser.value()?.into(),
+            #[cfg(feature = "tui")]
+            Long("archive-dir") => args.archive_dir = parser.value()?.into(),
+            Long("target") => args.target_mon = Some(parser.value()?.string()?),
+            Long("route") => args.route = Some(parser.value()?.string()?),
+            Long("method") => args.method = Some(parser.value()?.string()?),
+            #[cfg(feature = "tui")]
+            Long("odds") => args.shiny_odds = Some(parser.value()?.parse()?),
+            #[cfg(any(feature = "tui", feature = "http"))]
+            Long("recent") => args.recent_encounters = parser.value()?.parse()?,
+            #[cfg(feature = "tui")]
+            Long("webhook") => args.webhook_url = Some(parser.value()?.string()?),
+            #[cfg(feature = "tui")]
+            Long("notify-on") => args.notify_on = split_list(&parser.value()?.string()?),
+            #[cfg(feature = "tui")]
+            Long("notify-new-species") => args.notify_new_species = true,
+            Long("species") => args.species_path = Some(parser.value()?.into()),
+            Long("max-distance") => args.max_distance = parser.value()?.parse()?,
+            #[cfg(feature = "http")]
+            Long("http") => args.http_addr = Some(parser.value()?.string()?),
+            #[cfg(feature = "websocket")]
+            Long("websocket") => args.websocket_addr = Some(parser.value()?.string()?),
+            #[cfg(feature = "video")]
+            Long("video-interval") => args.video_interval_ms = parser.value()?.parse()?,
+            #[cfg(feature = "tokio")]
+            Long("tokio") => args.tokio = true,
+            #[cfg(feature = "global-hotkeys")]
+            Long("global-hotkeys") => args.global_hotkeys = true,
+            _ => return Err(arg.unexpected()),
+        }
+    }
+    if let Some(language) = args.language.clone() {
+        apply_language(&mut args, &language)?;
+    }
+    Ok(args)
+}
 
-    match fs::read(&detection_path) {
-        Ok(_) => (detection_path, recognition_path),
-        _ => (detection_path_exe, recognition_path_exe),
+/// Switches the recognition model, level markers and battle tokens to those of `name`
+/// in the `[languages]` config table.
+fn apply_language(args: &mut Args, name: &str) -> Result<(), String> {
+    let Some(language) = args.languages.get(name) else {
+        let mut available = args.languages.keys().cloned().collect::<Vec<_>>();
+        available.sort();
+        return Err(format!(
+            "language '{}' is not configured, available: {}",
+            name,
+            if available.is_empty() {
+                "none".to_string()
+            } else {
+                available.join(", ")
+            }
+        ));
+    };
+    if let Some(model) = &language.recognition_model {
+        if !model.is_file() {
+            return Err(format!(
+                "recognition model {} for language '{}' not found",
+                model.display(),
+                name
+            ));
+        }
     }
+
+    args.models.recognition = language.recognition_model.clone();
+    args.detection.level_markers = language.level_markers.clone();
+    args.detection.battle_tokens = language.battle_tokens.clone();
+    args.language = Some(name.to_string());
+    Ok(())
 }
 
+#[cfg(feature = "tui")]
 enum RunResult {
     Exit,
 }
 
+#[cfg(feature = "tui")]
 #[derive()]
 pub struct App {
     exit: bool,
     pub encounter_state: EncounterState,
-    engine: ocrs::OcrEngine,
+    /// Shared with the app a restart creates like `dictionary`.
+    engine: Rc<SkipStaticFrames<TimedOcr>>,
+    args: Args,
+    webhook: Option<notify::Webhook>,
+    /// Shared with the app a restart creates, see `try_to_restart`.
+    dictionary: Option<Rc<SpeciesDictionary>>,
+    mode_sender: Sender<Mode>,
+    mode_receiver: Receiver<Mode>,
+    session: SessionStats,
+    /// Targets reached with the last encounter, highlighted until the next one.
+    reached_targets: Vec<(String, u32)>,
+    should_stop: Arc<AtomicBool>,
+    #[cfg(feature = "http")]
+    server: Option<server::StateServer>,
+    #[cfg(feature = "websocket")]
+    events: Option<websocket::EventServer>,
+    #[cfg(feature = "global-hotkeys")]
+    global_hotkeys: Option<encounter::GlobalHotkeys>,
 }
 
+#[cfg(feature = "tui")]
 impl App {
-    fn new() -> Self {
+    /// Counts into `encounter_state`, see `load_state_or_default`. The engine and dictionary
+    /// are loaded by the caller, so bad models or a bad `--species` fail before the terminal UI
+    /// starts.
+    fn new(
+        args: Args,
+        encounter_state: EncounterState,
+        engine: Rc<SkipStaticFrames<TimedOcr>>,
+        dictionary: Option<Rc<SpeciesDictionary>>,
+    ) -> Self {
+        let (mode_sender, mode_receiver) = mpsc::channel();
         let mut t = Self {
             exit: false,
-            encounter_state: EncounterState::default(),
-            engine: init_engine().unwrap(),
-        };
-        if let Ok(loaded) = load_state() {
-            t.encounter_state = loaded;
-            t.encounter_state.mode = Mode::Init;
+            encounter_state,
+            engine,
+            webhook: args.webhook_url.clone().map(|url| {
+                notify::Webhook::new(url, args.notify_on.clone(), args.notify_new_species)
+            }),
+            dictionary,
+            mode_sender,
+            mode_receiver,
+            session: SessionStats::new(&EncounterState::default()),
+            reached_targets: vec![],
+            should_stop: Arc::new(AtomicBool::new(false)),
+            args,
+            #[cfg(feature = "http")]
+            server: None,
+            #[cfg(feature = "websocket")]
+            events: None,
+            #[cfg(feature = "global-hotkeys")]
+            global_hotkeys: None,
         };
+        t.encounter_state.mode = Mode::Init;
+        if let Some(target_mon) = &t.args.target_mon {
+            t.encounter_state.target_mon = Some(target_mon.clone());
+        }
+        if let Some(shiny_odds) = t.args.shiny_odds {
+            t.encounter_state.shiny_odds = shiny_odds;
+        }
+        if let Some(route) = &t.args.route {
+            t.encounter_state.set_route(route);
+        }
+        if let Some(method) = &t.args.method {
+            t.encounter_state.set_method(method);
+        }
+        t.session = SessionStats::new(&t.encounter_state);
         t
     }
 
     fn run(
         &mut self,
         terminal: &mut tui::Tui,
-        window: &Window,
+        source: &mut dyn ScreenSource,
     ) -> Result<RunResult, Box<dyn Error>> {
+        let store = ThrottledStore::new(
+            open_store(&self.args.state_path),
+            std::time::Duration::from_millis(self.args.detection.save_interval_ms),
+        );
         loop {
-            if self.exit {
+            if self.exit || self.should_stop.load(Ordering::Relaxed) {
+                // Flush the state so nothing counted since the last save is lost on the way out.
+                self.save();
                 return Ok(RunResult::Exit);
             }
 
             terminal.draw(|frame| self.render_frame(frame))?;
 
-            if encounter_process(&self.engine, &mut self.encounter_state, window).is_err() {
-                try_to_restart(terminal)?;
+            let filter = mode_filter(&self.args.detection, self.dictionary.as_deref());
+            match encounter_process(
+                self.engine.as_ref(),
+                &mut self.encounter_state,
+                source,
+                &self.args.detection,
+                filter.as_ref(),
+                &store,
+                &self.mode_receiver,
+            ) {
+                Ok(Some(record)) => self.on_encounter(&record),
+                Ok(None) => {}
+                Err(err) => {
+                    log::error!("detection failed, restarting: {}", err);
+                    return try_to_restart(terminal, self);
+                }
+            }
+            self.session.update(&self.encounter_state);
+
+            #[cfg(feature = "http")]
+            {
+                if let Some(server) = &self.server {
+                    server.publish(&self.encounter_state);
+                }
+            }
+            #[cfg(feature = "websocket")]
+            {
+                if let Some(events) = &self.events {
+                    events.publish_mode(&self.encounter_state);
+                }
             }
 
             self.process_keys()?;
         }
     }
 
+    fn save(&self) {
+        if let Err(err) = save_state(&self.encounter_state, &self.args.state_path) {
+            log::error!("can't save {}: {}", self.args.state_path.display(), err);
+        }
+    }
+
+    fn on_encounter(&mut self, record: &EncounterRecord) {
+        self.reached_targets = self
+            .encounter_state
+            .completed_targets(record, &self.args.targets);
+        for (species, target) in &self.reached_targets {
+            log::info!("target of {} {} reached", target, species);
+        }
+
+        #[cfg(feature = "websocket")]
+        {
+            if let Some(events) = &self.events {
+                events.encounter(record, &self.encounter_state);
+                for (species, target) in &self.reached_targets {
+                    events.target_complete(record, species, *target);
+                }
+            }
+        }
+        if let Some(webhook) = &self.webhook {
+            webhook.notify(record);
+            for (species, target) in &self.reached_targets {
+                webhook.notify_target_complete(record, species, *target);
+            }
+        }
+    }
+
     fn process_keys(&mut self) -> Result<(), Box<dyn Error>> {
+        #[cfg(feature = "global-hotkeys")]
+        {
+            let pressed = self.global_hotkeys.as_ref().map(|hotkeys| hotkeys.drain());
+            for action in pressed.into_iter().flatten() {
+                self.handle_action(action);
+            }
+        }
+
         if event::poll(std::time::Duration::from_millis(16))? {
             match event::read()? {
                 Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
@@ -133,17 +610,31 @@ impl App {
         self.exit = true;
     }
 
+    fn request_mode(&self, mode: Mode) {
+        // The receiver lives as long as the app, so sending can't fail.
+        let _ = self.mode_sender.send(mode);
+    }
+
     fn render_frame(&self, frame: &mut Frame) {
         let title = Title::from("Rencounter Counter".bold());
-        let instructions = get_instruction_line();
+        let instructions =
+            get_instruction_line(&self.args.hotkeys, !self.args.languages.is_empty());
         let block = get_block(title, instructions);
-        let top_five = self.get_top_five();
-        let encounter_text = self.get_encounter_text();
+        let inner = block.inner(frame.area());
+        frame.render_widget(block, frame.area());
 
-        let mut info_lines = self.get_info_lines(encounter_text);
-        prepare_info_lines_to_display(top_five, &mut info_lines);
+        let info_lines = self.get_info_lines(self.get_encounter_text());
+        let [info_area, table_area] = Layout::vertical([
+            Constraint::Length(info_lines.len() as u16),
+            Constraint::Min(0),
+        ])
+        .areas(inner);
+        let [table_area] = Layout::horizontal([Constraint::Length(TOP_SPECIES_WIDTH)])
+            .flex(Flex::Center)
+            .areas(table_area);
 
-        frame.render_widget(Paragraph::new(info_lines).block(block), frame.area());
+        frame.render_widget(Paragraph::new(info_lines), info_area);
+        frame.render_widget(self.get_top_species_table(), table_area);
     }
 
     fn get_encounter_text(&self) -> String {
@@ -155,25 +646,182 @@ impl App {
         encounter_text
     }
 
-    fn get_top_five(&self) -> Vec<(&String, &u32)> {
-        let mut top_five = self
+    fn get_chain_text(&self) -> String {
+        let (species, length) = &self.encounter_state.current_chain;
+        let longest = self
             .encounter_state
             .mon_stats
-            .iter()
-            .collect::<Vec<(&String, &u32)>>();
+            .get(species)
+            .map_or(0, |stat| stat.longest_chain);
+        match length {
+            0 => "-".to_string(),
+            _ => format!("{} x{} (best {})", species, length, longest),
+        }
+    }
+
+    fn get_route_text(&self) -> String {
+        let route = &self.encounter_state.current_route;
+        if route.is_empty() {
+            return "-".to_string();
+        }
+        let (encounters, battles) = self
+            .encounter_state
+            .route_stats()
+            .map_or((0, 0), |stats| (stats.encounters, stats.battle_count));
+        format!("{}: {} encounters, {} battles", route, encounters, battles)
+    }
+
+    fn get_method_text(&self) -> String {
+        let Some(method) = &self.encounter_state.current_method else {
+            return "-".to_string();
+        };
+        let (encounters, battles) = self
+            .encounter_state
+            .current_method_stats()
+            .map_or((0, 0), |stats| (stats.encounters, stats.battle_count));
+        format!("{}: {} encounters, {} battles", method, encounters, battles)
+    }
+
+    /// Most encountered species with their share, refreshed every detection cycle.
+    fn get_top_species_table(&self) -> Table<'_> {
+        let rows = self
+            .encounter_state
+            .summary(None)
+            .top_five
+            .into_iter()
+            .map(|(species, count, share)| {
+                Row::new(vec![species, count.to_string(), format!("{:.1}%", share)])
+            })
+            .collect::<Vec<_>>();
 
-        top_five.sort_by(|a, b| Ord::cmp(&b.1, &a.1));
-        top_five
+        Table::new(
+            rows,
+            [
+                Constraint::Min(16),
+                Constraint::Length(8),
+                Constraint::Length(7),
+            ],
+        )
+        .header(Row::new(vec!["Species", "Count", "Share"]).style(Style::new().bold()))
+    }
+
+    /// Newest first, nothing when `--recent` is 0 or nothing was counted yet.
+    fn get_recent_lines(&self) -> Vec<Line<'_>> {
+        let recent = self
+            .encounter_state
+            .recent_encounters(self.args.recent_encounters);
+        if recent.is_empty() {
+            return vec![];
+        }
+
+        let mut lines = vec![Line::from("Recent encounters").centered()];
+        for record in recent.iter().rev() {
+            lines.push(
+                Line::from(format!("#{} {}", record.encounter, record.mons.join(", "))).centered(),
+            );
+        }
+        lines.push(Line::from("").centered());
+        lines
+    }
+
+    fn get_odds_lines(&self) -> Vec<Line<'_>> {
+        let report = self.encounter_state.odds_report();
+        let mut lines = vec![
+            Line::from("Shiny chance").centered(),
+            Line::from(format!(
+                "{:.1}% after {} encounters (1/{})",
+                report.shiny_probability * 100.0,
+                report.encounters,
+                self.encounter_state.shiny_odds
+            ))
+            .centered(),
+        ];
+
+        lines.push(
+            Line::from(format!(
+                "90% chance within the next {} encounters",
+                self.encounter_state
+                    .expected_encounters_for_probability(SHINY_GOAL)
+            ))
+            .centered(),
+        );
+
+        if let Some(target_mon) = &self.encounter_state.target_mon {
+            lines.push(
+                Line::from(format!("{}: {}", target_mon, report.target_encounters))
+                    .yellow()
+                    .centered(),
+            );
+        }
+        lines.push(Line::from("").centered());
+        lines
+    }
+
+    fn get_target_lines(&self) -> Vec<Line<'_>> {
+        let remaining = self.encounter_state.remaining_targets(&self.args.targets);
+        if remaining.is_empty() {
+            return vec![];
+        }
+
+        let mut lines = vec![Line::from("Targets").centered()];
+        for (species, target) in &self.reached_targets {
+            lines.push(
+                Line::from(format!("{} {} reached!", target, species))
+                    .green()
+                    .bold()
+                    .centered(),
+            );
+        }
+        for (species, left) in remaining {
+            lines.push(match left {
+                0 => Line::from(format!("{}: done", species)).green().centered(),
+                _ => Line::from(format!("{}: {} left", species, left)).centered(),
+            });
+        }
+        lines.push(Line::from("").centered());
+        lines
     }
 
     fn get_info_lines(&self, encounter_text: String) -> Vec<Line<'_>> {
-        vec![
+        let mut lines = vec![
             Line::from("Encounter number").centered(),
             Line::from(encounter_text.to_string()).centered(),
             Line::from("").centered(),
+            Line::from("Battles").centered(),
+            Line::from(self.encounter_state.battle_count.to_string()).centered(),
+            Line::from("").centered(),
+            Line::from("Encounters per hour").centered(),
+            Line::from(format!("{:.0}", self.session.encounters_per_hour())).centered(),
+            Line::from("").centered(),
+            Line::from("Hunting time").centered(),
+            Line::from(encounter::hours_minutes(
+                self.encounter_state.active_duration(),
+            ))
+            .centered(),
+            Line::from("").centered(),
             Line::from("Last encounter").centered(),
             Line::from(format!("{:?}", self.encounter_state.last_encounter)).centered(),
             Line::from("").centered(),
+            Line::from("Chain").centered(),
+            Line::from(self.get_chain_text()).centered(),
+            Line::from("").centered(),
+            Line::from("Route").centered(),
+            Line::from(self.get_route_text()).centered(),
+            Line::from("").centered(),
+            Line::from("Method").centered(),
+            Line::from(self.get_method_text()).centered(),
+            Line::from("").centered(),
+            Line::from("Soft resets").centered(),
+            Line::from(format!(
+                "{} ({} since the last encounter)",
+                self.encounter_state.resets,
+                self.encounter_state.resets_since_encounter()
+            ))
+            .centered(),
+            Line::from("").centered(),
+            Line::from("Eggs hatched").centered(),
+            Line::from(format!("{}", self.encounter_state.eggs_hatched)).centered(),
+            Line::from("").centered(),
             Line::from("Lur").centered(),
             Line::from(format!("{}", self.encounter_state.lure_on))
                 .yellow()
@@ -185,53 +833,163 @@ impl App {
             Line::from("Game Mode").centered(),
             Line::from(format!("{}", self.encounter_state.toggle)).centered(),
             Line::from("").centered(),
-            Line::from("Top 5 encounters").centered(),
-        ]
+        ];
+        if let Some(language) = &self.args.language {
+            lines.push(Line::from("Language").centered());
+            lines.push(Line::from(language.clone()).centered());
+            lines.push(Line::from("").centered());
+        }
+        lines.extend(self.get_recent_lines());
+        lines.extend(self.get_odds_lines());
+        lines.extend(self.get_target_lines());
+        lines.push(Line::from("Top 5 encounters").centered());
+        lines
     }
 
     fn handle_key_event(&mut self, key_event: KeyEvent) {
-        match key_event.code {
-            KeyCode::Char('q') => self.exit(),
-            KeyCode::Char('s') => self.encounter_state.mode = Mode::Walk,
-            KeyCode::Char('d') => self.encounter_state.debug = !self.encounter_state.debug,
-            KeyCode::Char('p') => self.encounter_state.mode = Mode::Pause,
-            KeyCode::Char('t') => {
+        let KeyCode::Char(key) = key_event.code else {
+            return;
+        };
+        // Raw mode delivers Ctrl-C as a key instead of a signal.
+        if key == 'c' && key_event.modifiers.contains(KeyModifiers::CONTROL) {
+            self.exit();
+            return;
+        }
+        // The global listener sees the same key presses when Ctrl and Shift are held.
+        #[cfg(feature = "global-hotkeys")]
+        if self.global_hotkeys.is_some()
+            && key_event
+                .modifiers
+                .contains(KeyModifiers::CONTROL | KeyModifiers::SHIFT)
+        {
+            return;
+        }
+
+        if let Some(action) = self.args.hotkeys.action(key) {
+            self.handle_action(action);
+        }
+    }
+
+    /// Rebuilds the engine for the next configured language, keeps the current one when
+    /// that fails.
+    fn next_language(&mut self) {
+        let mut names = self.args.languages.keys().cloned().collect::<Vec<_>>();
+        names.sort();
+        let current = self
+            .args
+            .language
+            .as_ref()
+            .and_then(|language| names.iter().position(|name| name == language));
+        let Some(next) = current.map_or(names.first(), |i| names.get((i + 1) % names.len())) else {
+            return;
+        };
+
+        let mut args = self.args.clone();
+        let switched = apply_language(&mut args, next)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|()| detection_engine(&args));
+        match switched {
+            Ok(engine) => {
+                log::info!("switched to language {}", next);
+                self.engine = Rc::new(engine);
+                self.args = args;
+            }
+            Err(err) => log::error!("can't switch to language {}: {}", next, err),
+        }
+    }
+
+    fn handle_action(&mut self, action: HotkeyAction) {
+        match action {
+            HotkeyAction::Quit => self.exit(),
+            HotkeyAction::Start => self.request_mode(Mode::Walk),
+            HotkeyAction::Debug => self.encounter_state.debug = !self.encounter_state.debug,
+            HotkeyAction::NextLanguage => self.next_language(),
+            HotkeyAction::Pause => self.request_mode(Mode::Pause),
+            HotkeyAction::ManualIncrement => {
+                let record = self.encounter_state.record_manual(None);
+                self.on_encounter(&record);
+                self.save();
+            }
+            HotkeyAction::IgnoreLast => {
+                for mon in &self.encounter_state.last_encounter {
+                    log::info!("ignoring {} from now on", mon);
+                    self.args.detection.ignore(mon);
+                }
+            }
+            HotkeyAction::SoftReset => {
+                self.encounter_state.record_reset();
+                self.save();
+            }
+            HotkeyAction::ToggleGameMode => {
                 self.encounter_state.toggle = match self.encounter_state.toggle {
                     encounter::Toggle::Exp => encounter::Toggle::Runaway,
                     encounter::Toggle::Runaway => encounter::Toggle::Safari,
                     encounter::Toggle::Safari => encounter::Toggle::Exp,
                 };
             }
-            KeyCode::Char('r') => {
-                self.encounter_state = EncounterState::default();
-                save_state(&self.encounter_state).unwrap_or_default();
+            HotkeyAction::Reset => {
+                // Keep counting into the current state when it can't be archived.
+                if let Ok(fresh) = reset_state(&self.args.state_path, &self.args.archive_dir) {
+                    self.encounter_state = EncounterState {
+                        target_mon: self.encounter_state.target_mon.take(),
+                        current_route: std::mem::take(&mut self.encounter_state.current_route),
+                        current_method: self.encounter_state.current_method.take(),
+                        shiny_odds: self.encounter_state.shiny_odds,
+                        ..fresh
+                    };
+                    self.session = SessionStats::new(&self.encounter_state);
+                    self.save();
+                }
             }
-            _ => {}
         }
     }
 }
 
+#[cfg(feature = "tui")]
 fn try_to_restart(
     terminal: &mut ratatui::Terminal<ratatui::prelude::CrosstermBackend<std::io::Stdout>>,
+    app: &mut App,
 ) -> Result<RunResult, Box<dyn Error>> {
+    // Write what the throttled store held back, in case the game can't be found again.
+    app.save();
     terminal.clear()?;
-    if let Some(new_window) = Window::all()?.iter().find(encounter::game_exist) {
-        let mut new_app = App::new();
-        new_app.encounter_state.mode = Mode::Encounter;
-        new_app.run(terminal, new_window)
-    } else {
-        panic!("{} game not found", APP_NAME);
-    }
-}
+    let windows = game_windows()?;
+    let mut source = live_source(
+        &app.args.detection.capture,
+        windows.iter().find(encounter::game_exist),
+        frame_timeout(&app.args),
+    )?;
 
-fn prepare_info_lines_to_display(top_five: Vec<(&String, &u32)>, info_lines: &mut Vec<Line<'_>>) {
-    for i in 0..5 {
-        if let Some(mon) = top_five.get(i) {
-            info_lines.push(Line::from(format!("{}: {}", mon.0, mon.1)).centered());
-        }
+    let state = std::mem::take(&mut app.encounter_state);
+    let mut new_app = App::new(
+        app.args.clone(),
+        state,
+        Rc::clone(&app.engine),
+        app.dictionary.clone(),
+    );
+    new_app.session = app.session.clone();
+    new_app.should_stop = Arc::clone(&app.should_stop);
+    #[cfg(feature = "http")]
+    {
+        new_app.server = app.server.clone();
+    }
+    #[cfg(feature = "websocket")]
+    {
+        new_app.events = app.events.clone();
     }
+    #[cfg(feature = "global-hotkeys")]
+    {
+        new_app.global_hotkeys = app.global_hotkeys.clone();
+    }
+    new_app.encounter_state.mode = Mode::Encounter;
+    let result = new_app.run(terminal, source.as_mut());
+    // The hunt goes on in `new_app`, hand it back for the summary on exit.
+    app.encounter_state = new_app.encounter_state;
+    app.session = new_app.session;
+    result
 }
 
+#[cfg(feature = "tui")]
 fn get_block<'a>(title: Title<'a>, instructions: Title<'a>) -> Block<'a> {
     Block::default()
         .title(title.alignment(Alignment::Center))
@@ -244,56 +1002,237 @@ fn get_block<'a>(title: Title<'a>, instructions: Title<'a>) -> Block<'a> {
         .border_set(border::THICK)
 }
 
-fn get_instruction_line() -> Title<'static> {
-    Title::from(Line::from(vec![
+#[cfg(feature = "tui")]
+/// Lists the hotkeys, the language one only when `languages` are configured.
+fn get_instruction_line(hotkeys: &Hotkeys, languages: bool) -> Title<'static> {
+    let key = |key: char| format!(" <{}> ", key.to_uppercase()).blue().bold();
+    let mut spans = vec![
         " Start ".into(),
-        " <S> ".blue().bold(),
+        key(hotkeys.start),
         " Pause ".into(),
-        " <P> ".blue().bold(),
+        key(hotkeys.pause),
         " Reset ".into(),
-        " <R> ".blue().bold(),
+        key(hotkeys.reset),
+        " Add missed ".into(),
+        key(hotkeys.manual_increment),
+        " Soft reset ".into(),
+        key(hotkeys.soft_reset),
+        " Ignore last ".into(),
+        key(hotkeys.ignore_last),
         " GameMode ".into(),
-        " <T> ".blue().bold(),
+        key(hotkeys.toggle_game_mode),
         " Quit ".into(),
-        " <Q> ".blue().bold(),
+        key(hotkeys.quit),
         " Debug ".into(),
-        " <D> ".blue().bold(),
-    ]))
-}
-
-impl Default for App {
-    fn default() -> Self {
-        Self::new()
+        key(hotkeys.debug),
+    ];
+    if languages {
+        spans.extend([" Language ".into(), key(hotkeys.next_language)]);
     }
+    Title::from(Line::from(spans))
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let is_debug = env::args().find(|arg| arg == "debug");
+    let args = parse_args()?;
+    if let Some(log_path) = &args.log_path {
+        logging::init(log_path, args.log_level)?;
+    }
+
+    if args.list_displays {
+        for (index, width, height) in list_displays() {
+            println!("Display {}: {}x{}", index, width, height);
+        }
+        return Ok(());
+    }
+
+    if let Some(csv_path) = &args.export_csv {
+        let state = load_state(&args.state_path)?;
+        if args.export_seen {
+            state.export_seen_csv(csv_path)?;
+        } else {
+            state.export_csv(csv_path)?;
+        }
+        println!("Exported encounters to {}", csv_path.display());
+        return Ok(());
+    }
+
+    if let Some(csv_path) = &args.export_gaps {
+        let state = load_state(&args.state_path)?;
+        let species = args
+            .target_mon
+            .as_ref()
+            .or(state.target_mon.as_ref())
+            .ok_or("--export-gaps needs a --target mon")?;
+        state.export_gap_csv(species, csv_path)?;
+        println!(
+            "Exported gaps between {} to {}",
+            species,
+            csv_path.display()
+        );
+        return Ok(());
+    }
+
+    if let Some(other_path) = &args.merge_path {
+        let mut state = load_state_or_default(&args.state_path)?;
+        state.merge(&load_state(other_path)?);
+        save_state(&state, &args.state_path)?;
+        println!(
+            "Merged {} into {}, {} encounters",
+            other_path.display(),
+            args.state_path.display(),
+            state.encounters
+        );
+        return Ok(());
+    }
+
+    if args.recompute {
+        let mut state = load_state(&args.state_path)?;
+        let dictionary = load_dictionary(&args)?;
+        state.recompute_stats(mode_filter(&args.detection, dictionary.as_ref()).as_ref());
+        replace_state(&state, &args.state_path)?;
+        println!(
+            "Recomputed {}, {} encounters of {} species",
+            args.state_path.display(),
+            state.encounters,
+            state.mon_stats.len()
+        );
+        return Ok(());
+    }
 
-    if is_debug.is_some() {
+    if args.summary {
+        print!("{}", load_state(&args.state_path)?.summary(None));
+        return Ok(());
+    }
+
+    if args.print_state {
+        print_state(&load_state(&args.state_path)?, !args.compact)?;
+        return Ok(());
+    }
+
+    if args.debug {
         if let Some(value) = debug_mode() {
             return value;
         }
     }
 
-    if let Some(window) = Window::all().unwrap().iter().find(encounter::game_exist) {
-        let mut terminal = tui::init()?;
-        terminal.clear()?;
-
-        let mut app = App::default();
+    if let Some(path) = &args.calibrate_path {
+        let engine = build_engine(&args.models)?;
+        let image = image::open(path)?;
+        match calibrate(
+            &engine,
+            &image,
+            &args.detection.level_markers,
+            args.detection.line_padding,
+        )? {
+            Some(region) => println!(
+                "--region {},{},{},{}",
+                region.x, region.y, region.width, region.height
+            ),
+            None => println!("No level text found in {}", path.display()),
+        }
+        return Ok(());
+    }
 
-        if let Ok(RunResult::Exit) = app.run(&mut terminal, window) {
-            clear_terminal(terminal)?;
-            return Ok(());
+    if let Some(path) = &args.region_overlay_path {
+        let windows;
+        let mut source: Box<dyn ScreenSource + '_> = match &args.image_path {
+            Some(image) => Box::new(FileSource::new(image.clone())),
+            None => {
+                windows = game_windows()?;
+                screen_source(
+                    &args.detection.capture,
+                    windows.iter().find(encounter::game_exist),
+                )?
+            }
+        };
+        for region in debug_region_overlay(source.as_mut(), &args.detection.capture, path)? {
+            println!(
+                "region {}x{} at ({}, {})",
+                region.width, region.height, region.x, region.y
+            );
         }
+        println!("Saved region overlay to {}", path.display());
+        return Ok(());
+    }
+
+    if args.dry_run {
+        return dry_run(&args);
+    }
+
+    if let Some(dir) = &args.replay_dir {
+        return replay(&args, dir);
+    }
+
+    if let Some(dir) = &args.bench_dir {
+        return bench(&args, dir);
+    }
+
+    #[cfg(feature = "tui")]
+    if !args.stream {
+        return run_tui(args);
+    }
+    stream(&args)
+}
+
+/// The terminal UI, what runs without `--stream`.
+#[cfg(feature = "tui")]
+fn run_tui(args: Args) -> Result<(), Box<dyn Error>> {
+    let windows = game_windows()?;
+    let mut source = live_source(
+        &args.detection.capture,
+        windows.iter().find(encounter::game_exist),
+        frame_timeout(&args),
+    )?;
+
+    // Fail, or download, before the terminal UI takes over the screen.
+    args.models.locate()?;
+    let state = load_state_or_default(&args.state_path)?;
+    let engine = Rc::new(detection_engine(&args)?);
+    let dictionary = load_dictionary(&args)?.map(Rc::new);
+    let should_stop = stop_on_signal()?;
+    let mut terminal = tui::init()?;
+    terminal.clear()?;
 
+    #[cfg(feature = "http")]
+    let server = state_server(&args)?;
+    #[cfg(feature = "websocket")]
+    let events = event_server(&args)?;
+
+    #[cfg(feature = "global-hotkeys")]
+    let global_hotkeys = args
+        .global_hotkeys
+        .then(|| encounter::GlobalHotkeys::start(args.hotkeys.clone()));
+
+    let mut app = App::new(args, state, engine, dictionary);
+    app.should_stop = should_stop;
+    #[cfg(feature = "global-hotkeys")]
+    {
+        app.global_hotkeys = global_hotkeys;
+    }
+    #[cfg(feature = "http")]
+    {
+        app.server = server;
+    }
+    #[cfg(feature = "websocket")]
+    {
+        app.events = events;
+    }
+
+    if let Ok(RunResult::Exit) = app.run(&mut terminal, source.as_mut()) {
         clear_terminal(terminal)?;
-        Ok(())
-    } else {
-        panic!("{} game not found", APP_NAME);
+        print!(
+            "{}",
+            app.encounter_state
+                .summary(Some(app.session.active_duration()))
+        );
+        return Ok(());
     }
+
+    clear_terminal(terminal)?;
+    Ok(())
 }
 
+#[cfg(feature = "tui")]
 fn clear_terminal(
     mut terminal: ratatui::Terminal<ratatui::prelude::CrosstermBackend<std::io::Stdout>>,
 ) -> Result<(), Box<dyn Error>> {
@@ -302,15 +1241,338 @@ fn clear_terminal(
     Ok(())
 }
 
+/// How long capture and OCR may each take on a frame, see `--frame-timeout`.
+fn frame_timeout(args: &Args) -> Option<std::time::Duration> {
+    args.detection
+        .frame_timeout_ms
+        .map(std::time::Duration::from_millis)
+}
+
+/// The recognizer for live detection, see `--frame-timeout` and `--static-threshold`.
+fn detection_engine(args: &Args) -> Result<SkipStaticFrames<TimedOcr>, Box<dyn Error>> {
+    Ok(SkipStaticFrames::new(
+        TimedOcr::new(build_engine(&args.models)?, frame_timeout(args)),
+        args.detection.static_frame_threshold,
+    ))
+}
+
+fn load_dictionary(args: &Args) -> Result<Option<SpeciesDictionary>, Box<dyn Error>> {
+    args.species_path
+        .as_ref()
+        .map(|path| {
+            SpeciesDictionary::load(path, args.max_distance)
+                .map_err(|e| format!("can't load species {}: {}", path.display(), e).into())
+        })
+        .transpose()
+}
+
+fn replay(args: &Args, dir: &Path) -> Result<(), Box<dyn Error>> {
+    let engine = SkipStaticFrames::new(
+        TimedOcr::new(build_engine(&args.models)?, None),
+        args.detection.static_frame_threshold,
+    );
+    let dictionary = load_dictionary(args)?;
+    let filter = mode_filter(&args.detection, dictionary.as_ref());
+
+    let mut state = EncounterState::default();
+    #[cfg(feature = "video")]
+    let records = if dir.is_file() {
+        let interval = std::time::Duration::from_millis(args.video_interval_ms);
+        let mut video = encounter::VideoSource::open(dir, interval)?;
+        encounter::replay_video(
+            &engine,
+            &mut video,
+            &mut state,
+            &args.detection,
+            filter.as_ref(),
+        )?
+    } else {
+        replay_dir(&engine, dir, &mut state, &args.detection, filter.as_ref())?
+    };
+    #[cfg(not(feature = "video"))]
+    let records = replay_dir(&engine, dir, &mut state, &args.detection, filter.as_ref())?;
+    for record in records {
+        println!("#{}: {}", record.encounter, record.mons.join(", "));
+    }
+    println!(
+        "{} encounters in {} battles",
+        state.encounters, state.battle_count
+    );
+    Ok(())
+}
+
+/// `--bench`, prints the total and mean time per frame of every stage. Static frames are not
+/// skipped, every frame goes through OCR.
+fn bench(args: &Args, dir: &Path) -> Result<(), Box<dyn Error>> {
+    let engine = build_engine(&args.models)?;
+    let dictionary = load_dictionary(args)?;
+    let filter = mode_filter(&args.detection, dictionary.as_ref());
+
+    let (frames, timings) = bench_dir(
+        &engine,
+        dir,
+        args.bench_rounds,
+        &args.detection,
+        filter.as_ref(),
+    )?;
+    println!("{} frames", frames);
+    println!("total: {}", timings);
+    println!("per frame: {}", timings.per_frame(frames));
+    Ok(())
+}
+
+/// The `--http` server, if one was asked for.
+#[cfg(feature = "http")]
+fn state_server(args: &Args) -> std::io::Result<Option<server::StateServer>> {
+    args.http_addr
+        .as_deref()
+        .map(|addr| server::StateServer::start(addr, args.recent_encounters))
+        .transpose()
+}
+
+/// The `--websocket` server, if one was asked for.
+#[cfg(feature = "websocket")]
+fn event_server(args: &Args) -> std::io::Result<Option<websocket::EventServer>> {
+    args.websocket_addr
+        .as_deref()
+        .map(websocket::EventServer::start)
+        .transpose()
+}
+
+fn stream(args: &Args) -> Result<(), Box<dyn Error>> {
+    if !args.instances.is_empty() {
+        return stream_instances(args);
+    }
+
+    let windows = game_windows()?;
+    let mut source = live_source(
+        &args.detection.capture,
+        windows.iter().find(encounter::game_exist),
+        frame_timeout(args),
+    )?;
+
+    let dictionary = load_dictionary(args)?;
+    let mut filter = mode_filter(&args.detection, dictionary.as_ref());
+    if args.confirm {
+        filter = Box::new(ReviewedFilter::new(filter, Box::new(confirm_on_terminal)));
+    }
+
+    let store = ThrottledStore::new(
+        open_store(&args.state_path),
+        std::time::Duration::from_millis(args.detection.save_interval_ms),
+    );
+    let mut state = load_state_or_default(&args.state_path)?;
+    state.mode = Mode::Walk;
+    if let Some(route) = &args.route {
+        state.set_route(route);
+    }
+    if let Some(method) = &args.method {
+        state.set_method(method);
+    }
+    // Nothing pauses a stream, it runs until the process is stopped.
+    let (_mode_sender, mode_receiver) = mpsc::channel();
+    let should_stop = stop_on_signal()?;
+    #[cfg(feature = "http")]
+    let server = state_server(args)?;
+    #[cfg(feature = "websocket")]
+    let events = event_server(args)?;
+    #[cfg(feature = "tokio")]
+    if args.tokio {
+        let engine = Arc::new(build_engine(&args.models)?);
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+        runtime.block_on(async {
+            while !should_stop.load(Ordering::Relaxed) {
+                let record = encounter::encounter_process_async(
+                    &engine,
+                    &mut state,
+                    source.as_mut(),
+                    &args.detection,
+                    filter.as_ref(),
+                    &store,
+                    &mode_receiver,
+                )
+                .await?;
+                if let Some(record) = record {
+                    println!("{}", serde_json::to_string(&record)?);
+                    #[cfg(feature = "http")]
+                    if let Some(server) = &server {
+                        server.publish(&state);
+                    }
+                    #[cfg(feature = "websocket")]
+                    if let Some(events) = &events {
+                        events.encounter(&record, &state);
+                        events.publish_mode(&state);
+                    }
+                }
+            }
+            Ok::<(), Box<dyn Error>>(())
+        })?;
+        save_state(&state, &args.state_path)?;
+        return Ok(());
+    }
+
+    let engine = detection_engine(args)?;
+    let mut records = encounter_stream(
+        &engine,
+        &mut state,
+        source.as_mut(),
+        &args.detection,
+        filter.as_ref(),
+        &store,
+        &mode_receiver,
+    )
+    .with_stop(should_stop);
+    while let Some(record) = records.next() {
+        let record = record?;
+        println!("{}", serde_json::to_string(&record)?);
+        #[cfg(feature = "http")]
+        if let Some(server) = &server {
+            server.publish(records.state());
+        }
+        #[cfg(feature = "websocket")]
+        if let Some(events) = &events {
+            events.encounter(&record, records.state());
+            events.publish_mode(records.state());
+        }
+    }
+    drop(records);
+    // Cycles hold back saves within the save interval, this writes what they held back.
+    save_state(&state, &args.state_path)?;
+    Ok(())
+}
+
+/// `--stream` over every `--instance`, stepping their trackers in turn with one OCR engine.
+/// Static frames are not skipped, the last frame seen would be from another instance.
+fn stream_instances(args: &Args) -> Result<(), Box<dyn Error>> {
+    let windows = game_windows()?;
+    let mut trackers = vec![];
+    for (config_path, state_path) in &args.instances {
+        let config = load_config(config_path)
+            .map_err(|e| format!("can't load config {}: {}", config_path.display(), e))?
+            .detection;
+        let source = live_source(
+            &config.capture,
+            windows.iter().find(encounter::game_exist),
+            frame_timeout(args),
+        )?;
+        let store = ThrottledStore::new(
+            open_store(state_path),
+            std::time::Duration::from_millis(config.save_interval_ms),
+        );
+        let mut tracker = Tracker::new(config, source, Box::new(store))
+            .map_err(|e| format!("can't load state {}: {}", state_path.display(), e))?
+            .with_dictionary(load_dictionary(args)?);
+        tracker.request_mode(Mode::Walk);
+        trackers.push(tracker);
+    }
+
+    let engine = TimedOcr::new(build_engine(&args.models)?, frame_timeout(args));
+    let should_stop = stop_on_signal()?;
+    while !should_stop.load(Ordering::Relaxed) {
+        for (instance, tracker) in trackers.iter_mut().enumerate() {
+            if let Some(record) = tracker.step(&engine)? {
+                let line = serde_json::json!({ "instance": instance, "record": record });
+                println!("{}", line);
+            }
+        }
+    }
+    // Like `stream`, write what the throttled stores held back.
+    for (tracker, (_, state_path)) in trackers.iter().zip(&args.instances) {
+        save_state(&tracker.state, state_path)?;
+    }
+    Ok(())
+}
+
+/// Asks on stderr whether to count `mons`, see `--confirm`. Reads the answer from stdin,
+/// so it only works while nothing else reads it.
+fn confirm_on_terminal(mons: &[String]) -> Vec<String> {
+    eprint!(
+        "count {}? Enter to accept, n to reject or the names separated by commas: ",
+        mons.join(", ")
+    );
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return mons.to_vec();
+    }
+    match answer.trim() {
+        "" | "y" => mons.to_vec(),
+        "n" => vec![],
+        names => split_list(names),
+    }
+}
+
+fn dry_run(args: &Args) -> Result<(), Box<dyn Error>> {
+    // Screenshots are read without a display, e.g. on CI.
+    let windows;
+    let mut source: Box<dyn ScreenSource + '_> = match &args.image_path {
+        Some(path) => Box::new(FileSource::new(path.clone())),
+        None => {
+            windows = game_windows()?;
+            screen_source(
+                &args.detection.capture,
+                windows.iter().find(encounter::game_exist),
+            )?
+        }
+    };
+
+    let engine = build_engine(&args.models)?;
+    let dictionary = load_dictionary(args)?;
+    let filter = mode_filter(&args.detection, dictionary.as_ref());
+    let previews = preview_detection(&engine, source.as_mut(), &args.detection, filter.as_ref())?;
+    for (i, preview) in previews.into_iter().enumerate() {
+        for word in &preview.words {
+            match &word.discarded {
+                Some(reason) => println!("{} (discarded: {})", word.text, reason),
+                None if word.name != word.text => {
+                    println!("{} (counted as {})", word.text, word.name)
+                }
+                None => println!("{} (counted)", word.text),
+            }
+        }
+
+        if let Some(overlay_path) = &args.overlay_path {
+            let path = match i {
+                0 => overlay_path.clone(),
+                _ => overlay_path.with_file_name(format!(
+                    "{}-{}.png",
+                    overlay_path
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy(),
+                    i
+                )),
+            };
+            for (text, rect) in &preview.trace.lines {
+                println!(
+                    "region {} line '{}' at ({}, {})-({}, {})",
+                    i, text, rect.left, rect.top, rect.right, rect.bottom
+                );
+            }
+            let mut image = preview.image;
+            preview.trace.draw(&mut image);
+            image.save(&path)?;
+            println!("Saved OCR overlay to {}", path.display());
+        }
+    }
+    Ok(())
+}
+
 fn debug_mode() -> Option<Result<(), Box<dyn Error>>> {
     let (exe_path, path) = get_current_working_dir();
     println!("The current directory is {path} exe path {exe_path}",);
-    for window in Window::all().unwrap().iter() {
+    let windows = match game_windows() {
+        Ok(windows) => windows,
+        Err(err) => return Some(Err(err.into())),
+    };
+    for window in windows.iter() {
         println!("Window: {:?}", (window.app_name(), window.title()));
 
         if window.title().to_lowercase() == APP_NAME || window.app_name() == APP_NAME {
-            let img = window.capture_image().unwrap();
-            let _ = img.save("debug.png");
+            if let Ok(img) = window.capture_image() {
+                let _ = img.save("debug.png");
+            }
         }
     }
     Some(Ok(()))
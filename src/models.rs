@@ -0,0 +1,133 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ocrs::{OcrEngine, OcrEngineParams};
+
+use crate::encounter::get_current_working_dir;
+
+const DETECTION_MODEL: &str = "text-detection.rten";
+const RECOGNITION_MODEL: &str = "text-recognition.rten";
+const MODEL_URL: &str = "https://ocrs-models.s3-accelerate.amazonaws.com";
+const CACHE_DIR_NAME: &str = "rencounter_counter";
+
+/// Where the OCR models are read from. Models without a path are looked up in the
+/// working directory, next to the executable and in the cache directory, in that order.
+#[derive(Debug, Clone, Default)]
+pub struct ModelPaths {
+    pub detection: Option<PathBuf>,
+    pub recognition: Option<PathBuf>,
+    /// Download models that are not found anywhere into the cache directory.
+    pub download: bool,
+    /// Threads OCR runs on, one per core when `None`. Only the first engine built in a
+    /// process sets it.
+    pub threads: Option<usize>,
+}
+
+/// Builds the OCR engine the counter uses. Only the models and the thread count are
+/// configurable, every other `OcrEngineParams` option keeps its ocrs default.
+pub fn build_engine(models: &ModelPaths) -> Result<OcrEngine, Box<dyn Error>> {
+    if let Some(threads) = models.threads {
+        // rten sizes its own pool from this variable the first time it runs a model.
+        env::set_var("RTEN_NUM_THREADS", threads.to_string());
+        // Fails when the global pool already exists, e.g. for an engine rebuilt after a restart.
+        let _ = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global();
+    }
+
+    let (detection_model, recognition_model) = models.load()?;
+    let engine = OcrEngine::new(OcrEngineParams {
+        detection_model: Some(detection_model),
+        recognition_model: Some(recognition_model),
+        ..Default::default()
+    })?;
+    Ok(engine)
+}
+
+impl ModelPaths {
+    /// Finds the detection and recognition model, downloading them when enabled.
+    pub fn locate(&self) -> Result<(PathBuf, PathBuf), Box<dyn Error>> {
+        let detection = self.resolve(self.detection.as_deref(), DETECTION_MODEL, "detection")?;
+        let recognition = self.resolve(
+            self.recognition.as_deref(),
+            RECOGNITION_MODEL,
+            "recognition",
+        )?;
+        Ok((detection, recognition))
+    }
+
+    /// Loads the detection and recognition model found by `locate`.
+    pub fn load(&self) -> Result<(rten::Model, rten::Model), Box<dyn Error>> {
+        let (detection, recognition) = self.locate()?;
+        let detection_model = rten::Model::load(fs::read(detection)?)?;
+        let recognition_model = rten::Model::load(fs::read(recognition)?)?;
+        Ok((detection_model, recognition_model))
+    }
+
+    fn resolve(
+        &self,
+        path: Option<&Path>,
+        file_name: &str,
+        kind: &str,
+    ) -> Result<PathBuf, Box<dyn Error>> {
+        if let Some(path) = path {
+            if !path.is_file() {
+                return Err(format!("{} model {} not found", kind, path.display()).into());
+            }
+            return Ok(path.to_path_buf());
+        }
+
+        let (exe_path, path) = get_current_working_dir();
+        let mut dirs = vec![PathBuf::from(path), PathBuf::from(exe_path)];
+        dirs.extend(cache_dir());
+        let candidates = dirs
+            .iter()
+            .map(|dir| dir.join(file_name))
+            .collect::<Vec<_>>();
+        if let Some(found) = candidates.iter().find(|candidate| candidate.is_file()) {
+            return Ok(found.clone());
+        }
+
+        if let (true, Some(dir)) = (self.download, cache_dir()) {
+            return download(file_name, &dir);
+        }
+
+        let searched = candidates
+            .iter()
+            .map(|candidate| candidate.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        Err(format!(
+            "can't find the {} model, looked for {}. Pass its path with --{}-model or run once with --download-models",
+            kind, searched, kind
+        )
+        .into())
+    }
+}
+
+/// Per user cache directory, `None` when the platform has none.
+fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .or_else(|| env::var_os("LOCALAPPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|base| base.join(CACHE_DIR_NAME))
+}
+
+/// Fetches `file_name` from the ocrs model bucket into `dir`, through a temporary
+/// file so an interrupted download is not picked up as a model.
+fn download(file_name: &str, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let url = format!("{}/{}", MODEL_URL, file_name);
+    eprintln!("downloading {} to {}", url, dir.display());
+
+    fs::create_dir_all(dir)?;
+    let path = dir.join(file_name);
+    let tmp_path = dir.join(format!("{}.part", file_name));
+    let mut reader = ureq::get(&url).call()?.into_reader();
+    io::copy(&mut reader, &mut fs::File::create(&tmp_path)?)?;
+    fs::rename(&tmp_path, &path)?;
+    Ok(path)
+}
@@ -0,0 +1,74 @@
+use std::thread;
+
+use crate::encounter::EncounterRecord;
+
+/// Posts a JSON payload to a webhook (Discord, ntfy.sh, ...) whenever one of the watched mons,
+/// or optionally a species never seen before, is encountered.
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    url: String,
+    notify_on: Vec<String>,
+    notify_new_species: bool,
+}
+
+impl Webhook {
+    pub fn new(url: String, notify_on: Vec<String>, notify_new_species: bool) -> Self {
+        Self {
+            url,
+            notify_on,
+            notify_new_species,
+        }
+    }
+
+    /// Fires one request per watched mon in `record` on a detached thread, failures are only reported.
+    /// New species get their own request with `"event": "new_species"`.
+    pub fn notify(&self, record: &EncounterRecord) {
+        for species in record.mons.iter().filter(|mon| {
+            self.notify_on
+                .iter()
+                .any(|watched| watched.eq_ignore_ascii_case(mon))
+        }) {
+            self.post(serde_json::json!({
+                "species": species,
+                "encounter": record.encounter,
+                "timestamp": record.timestamp,
+            }));
+        }
+
+        if self.notify_new_species {
+            for species in &record.new_species {
+                self.post(serde_json::json!({
+                    "event": "new_species",
+                    "species": species,
+                    "encounter": record.encounter,
+                    "timestamp": record.timestamp,
+                }));
+            }
+        }
+    }
+
+    /// Posts `"event": "target_complete"` once `species` was encountered `target` times.
+    pub fn notify_target_complete(&self, record: &EncounterRecord, species: &str, target: u32) {
+        self.post(serde_json::json!({
+            "event": "target_complete",
+            "species": species,
+            "target": target,
+            "encounter": record.encounter,
+            "timestamp": record.timestamp,
+        }));
+    }
+
+    fn post(&self, payload: serde_json::Value) {
+        let url = self.url.clone();
+        let payload = payload.to_string();
+
+        thread::spawn(move || {
+            if let Err(err) = ureq::post(&url)
+                .set("Content-Type", "application/json")
+                .send_string(&payload)
+            {
+                log::warn!("webhook {} failed: {}", url, err);
+            }
+        });
+    }
+}
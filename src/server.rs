@@ -0,0 +1,159 @@
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::encounter::{EncounterState, Mode};
+
+/// How long a client may take to send its request before the connection is dropped.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+struct Snapshot {
+    state_json: String,
+    count: u32,
+    recent_json: String,
+    metrics: String,
+}
+
+/// Serves the latest published state for stream overlays:
+/// `/state` returns the whole state as JSON, `/count` the encounter number as plain text,
+/// `/recent` the last `recent` encounters as a JSON array and `/metrics` the counters in the
+/// Prometheus text format.
+#[derive(Debug, Clone)]
+pub struct StateServer {
+    snapshot: Arc<Mutex<Snapshot>>,
+    recent: usize,
+}
+
+impl StateServer {
+    /// Binds to `addr` (e.g. `127.0.0.1:8080`) and accepts connections on a background thread.
+    /// Every connection is answered on a thread of its own, so an idle client, like a browser
+    /// source that connects ahead of time, can't hold up the others.
+    pub fn start(addr: &str, recent: usize) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let snapshot = Arc::new(Mutex::new(Snapshot::default()));
+
+        let shared = Arc::clone(&snapshot);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || handle_request(stream, &shared));
+            }
+        });
+
+        Ok(Self { snapshot, recent })
+    }
+
+    pub fn publish(&self, state: &EncounterState) {
+        if let (Ok(state_json), Ok(recent_json), Ok(mut snapshot)) = (
+            serde_json::to_string(state),
+            serde_json::to_string(state.recent_encounters(self.recent)),
+            self.snapshot.lock(),
+        ) {
+            snapshot.state_json = state_json;
+            snapshot.count = state.encounters;
+            snapshot.recent_json = recent_json;
+            snapshot.metrics = render_metrics(state);
+        }
+    }
+}
+
+/// Escapes a Prometheus label value.
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn render_metrics(state: &EncounterState) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# HELP rencounter_total Mons encountered in this hunt."
+    );
+    let _ = writeln!(out, "# TYPE rencounter_total counter");
+    let _ = writeln!(out, "rencounter_total {}", state.encounters);
+
+    let _ = writeln!(
+        out,
+        "# HELP rencounter_battles_total Battles counted in this hunt."
+    );
+    let _ = writeln!(out, "# TYPE rencounter_battles_total counter");
+    let _ = writeln!(out, "rencounter_battles_total {}", state.battle_count);
+
+    let mut species = state.mon_stats.iter().collect::<Vec<_>>();
+    species.sort_by(|a, b| a.0.cmp(b.0));
+    let _ = writeln!(
+        out,
+        "# HELP rencounter_species_total Encounters per species."
+    );
+    let _ = writeln!(out, "# TYPE rencounter_species_total counter");
+    for (name, stat) in species {
+        let _ = writeln!(
+            out,
+            "rencounter_species_total{{species=\"{}\"}} {}",
+            label(name),
+            stat.count
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP rencounter_mode Current mode of the counter, 1 for the active one."
+    );
+    let _ = writeln!(out, "# TYPE rencounter_mode gauge");
+    for (mode, name) in [
+        (Mode::Init, "init"),
+        (Mode::Encounter, "encounter"),
+        (Mode::Walk, "walk"),
+        (Mode::Pause, "pause"),
+    ] {
+        let active = u8::from(state.mode == mode);
+        let _ = writeln!(out, "rencounter_mode{{mode=\"{}\"}} {}", name, active);
+    }
+    out
+}
+
+fn handle_request(mut stream: TcpStream, snapshot: &Mutex<Snapshot>) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Drain the headers, the request body is never needed.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let (status, content_type, body) = {
+        let snapshot = snapshot
+            .lock()
+            .map_err(|_| io::Error::other("state lock poisoned"))?;
+        match path {
+            "/state" => ("200 OK", "application/json", snapshot.state_json.clone()),
+            "/count" => ("200 OK", "text/plain", snapshot.count.to_string()),
+            "/recent" => ("200 OK", "application/json", snapshot.recent_json.clone()),
+            "/metrics" => (
+                "200 OK",
+                "text/plain; version=0.0.4",
+                snapshot.metrics.clone(),
+            ),
+            _ => ("404 Not Found", "text/plain", "not found".to_string()),
+        }
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
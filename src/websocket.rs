@@ -0,0 +1,118 @@
+use serde::Serialize;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tungstenite::{Message, WebSocket};
+
+use crate::encounter::{EncounterRecord, EncounterState, Mode};
+
+/// A client that doesn't take a message within this long is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// A client that doesn't finish its handshake within this long is dropped.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Clients = Arc<Mutex<Vec<WebSocket<TcpStream>>>>;
+
+/// Messages pushed to every client, as JSON tagged by `type`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EncounterEvent<'a> {
+    /// An encounter was counted, `encounters` is the total after it.
+    Encounter {
+        record: &'a EncounterRecord,
+        encounters: u32,
+        battle_count: u32,
+    },
+    /// The counter switched to `mode`.
+    Mode { mode: &'a Mode },
+    /// `species` was encountered `target` times with `record`.
+    TargetComplete {
+        record: &'a EncounterRecord,
+        species: &'a str,
+        target: u32,
+    },
+}
+
+/// Pushes every counted encounter and mode change to connected WebSocket clients,
+/// so overlays don't have to poll `/state`.
+#[derive(Clone)]
+pub struct EventServer {
+    clients: Clients,
+    last_mode: Arc<Mutex<Option<Mode>>>,
+}
+
+impl EventServer {
+    /// Binds to `addr` (e.g. `127.0.0.1:8081`) and accepts clients on a background thread.
+    pub fn start(addr: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let clients = Arc::new(Mutex::new(vec![]));
+
+        let shared = Arc::clone(&clients);
+        thread::spawn(move || {
+            // One thread per handshake, so a client that never finishes it can't hold up the rest.
+            for stream in listener.incoming().flatten() {
+                let shared = Arc::clone(&shared);
+                thread::spawn(move || accept_client(stream, &shared));
+            }
+        });
+
+        Ok(Self {
+            clients,
+            last_mode: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    pub fn encounter(&self, record: &EncounterRecord, state: &EncounterState) {
+        self.send(&EncounterEvent::Encounter {
+            record,
+            encounters: state.encounters,
+            battle_count: state.battle_count,
+        });
+    }
+
+    pub fn target_complete(&self, record: &EncounterRecord, species: &str, target: u32) {
+        self.send(&EncounterEvent::TargetComplete {
+            record,
+            species,
+            target,
+        });
+    }
+
+    /// Sends the mode of `state` when it changed since the last call.
+    pub fn publish_mode(&self, state: &EncounterState) {
+        let Ok(mut last_mode) = self.last_mode.lock() else {
+            return;
+        };
+        if last_mode.as_ref() != Some(&state.mode) {
+            *last_mode = Some(state.mode.clone());
+            self.send(&EncounterEvent::Mode { mode: &state.mode });
+        }
+    }
+
+    /// Clients that can't be written to anymore are dropped.
+    fn send(&self, event: &EncounterEvent) {
+        let (Ok(json), Ok(mut clients)) = (serde_json::to_string(event), self.clients.lock())
+        else {
+            return;
+        };
+        clients.retain_mut(|client| client.send(Message::text(json.clone())).is_ok());
+    }
+}
+
+fn accept_client(stream: TcpStream, clients: &Clients) {
+    if stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT)).is_err()
+        || stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err()
+    {
+        return;
+    }
+    match tungstenite::accept(stream) {
+        Ok(client) => {
+            if let Ok(mut clients) = clients.lock() {
+                clients.push(client);
+            }
+        }
+        Err(err) => log::debug!("websocket handshake failed: {}", err),
+    }
+}